@@ -1,7 +1,9 @@
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
 use std::env;
+use std::cmp::Ordering;
 use std::collections::BTreeMap ;
+use std::fmt;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -9,17 +11,37 @@ fn main() -> io::Result<()> {
 
     let problem = read_input(input)?;
 
+    if args.iter().any(|arg| arg == "--table") {
+        print_ranking(&problem);
+    }
+
     println!("{:?} are the total winnings", solve1(&problem));
 
     Ok(())
 }
+
 fn solve1(problem: &Problem) -> usize {
-    let mut players = problem.players.clone();
+    ranking(problem).iter().map(|(rank, player)| rank * player.bet).sum()
+}
+
+/* Players ordered from weakest to strongest hand, paired with their (1-based) rank */
+fn ranking(problem: &Problem) -> Vec<(usize, &Player)> {
+    let mut players: Vec<&Player> = problem.players.iter().collect();
     players.sort();
 
-    players.iter().rev().enumerate().map(|(i, player)| {
-            (i+1) * player.bet
-    }).sum()
+    players.into_iter().enumerate().map(|(i, player)| (i + 1, player)).collect()
+}
+
+fn print_ranking(problem: &Problem) {
+    println!("{:>5} {:>5} {:<12} {:>5} {:>10}", "rank", "hand", "type", "bid", "winnings");
+    for (rank, player) in ranking(problem) {
+        println!("{:>5} {:>5} {:<12} {:>5} {:>10}",
+                 rank,
+                 player.hand.to_string(),
+                 format!("{:?}", player.hand.best_hand),
+                 player.bet,
+                 rank * player.bet);
+    }
 }
 
 #[derive(Clone)]
@@ -27,19 +49,29 @@ struct Problem {
     players: Vec<Player>
 }
 
-#[derive(Clone, PartialEq, Eq, Ord)]
+/* Players are ordered by hand first, ties between identical hands are broken on the bet */
+#[derive(Clone, PartialEq, Eq)]
 struct Player{
     hand: Hand,
     bet: usize
 }
 
+impl Ord for Player {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hand.cmp(&other.hand)
+            .then_with(|| self.bet.cmp(&other.bet))
+    }
+}
+
 impl PartialOrd for Player {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.hand.partial_cmp(&other.hand)
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-#[derive(Clone, Eq, Ord)]
+/* Hands are ordered by best type first and then card by card, so stronger hands compare greater.
+   best_hand is derived from the cards, so comparing the cards alone is enough for equality. */
+#[derive(Clone, Eq)]
 struct Hand {
     cards: Vec<Card>,
     best_hand: HandType,
@@ -47,32 +79,51 @@ struct Hand {
 
 impl PartialEq for Hand {
     fn eq(&self, other: &Self) -> bool {
-        // Define how two structs are considered equal
-        self.cards == other.cards // Example: compare based on `field1`
+        self.cards == other.cards
+    }
+}
+
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.best_hand.cmp(&other.best_hand)
+            .then_with(|| self.cards.cmp(&other.cards))
     }
 }
 
 impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // Define how two structs are ordered
-        let ordering = self.best_hand.partial_cmp(&other.best_hand); // Example: compare based on `field1`
-        if ordering.is_some() && ordering.unwrap() == std::cmp::Ordering::Equal {
-            for i in 0..(self.cards.len()) {
-                let l = self.cards.get(i).unwrap();
-                let r = other.cards.get(i).unwrap();
-                let o = l.cmp(r);
-                if o != std::cmp::Ordering::Equal {
-                    return Some(o)
-                }
-            }
-            ordering
-        } else {
-            ordering
-        }
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cards: String = self.cards.iter().map(|card| card.to_char()).collect();
+        write!(f, "{}", cards)
+    }
+}
+
+fn get_best_type(cards: &[Card]) -> HandType {
+    // A joker always does best by copying the card we already hold most of, so
+    // the best type follows directly from the multiplicities of the other cards.
+    let jokers = cards.iter().filter(|card| card == &&Card::Joker).count();
+
+    let others: Vec<Card> =
+        cards.iter().filter(|card| card != &&Card::Joker).cloned().collect();
+
+    let mut multiplicities = get_multiplicities(&others);
+
+    match multiplicities.first_mut() {
+        Some(largest) => *largest += jokers,
+        None => multiplicities.push(jokers),
     }
+
+    HandType::from_multiplicities(&multiplicities)
 }
 
-fn get_best_type(cards: Vec<Card>) -> HandType {
+/* Exhaustive reference for get_best_type, only used to cross-check it in tests */
+#[cfg(test)]
+fn get_best_type_exhaustive(cards: Vec<Card>) -> HandType {
 
     let joker_index =
         cards.iter()
@@ -104,68 +155,117 @@ fn get_best_type(cards: Vec<Card>) -> HandType {
     other_cards.iter().map(|other_card| {
         let mut next_cards = cards.clone();
         next_cards[joker_index] = other_card.clone();
-        get_best_type(next_cards)
-    }).min()
+        get_best_type_exhaustive(next_cards)
+    }).max()
       .unwrap()
 }
 
+#[cfg(test)]
+fn get_type(cards: &[Card]) -> HandType {
+    HandType::from_multiplicities(&get_multiplicities(cards))
+}
 
-fn get_type(cards: &Vec<Card>) -> HandType {
-    let mut card2amount: BTreeMap<Card, u32> = BTreeMap::new();
+/* How often each distinct card occurs, largest first */
+fn get_multiplicities(cards: &[Card]) -> Vec<usize> {
+    let mut card2amount: BTreeMap<&Card, usize> = BTreeMap::new();
 
     for c in cards {
-        *card2amount.entry(c.clone()).or_insert(0) += 1;
+        *card2amount.entry(c).or_insert(0) += 1;
     }
 
-    if card2amount.len() == 1 {
-        HandType::FiveOfAKind
-    } else if card2amount.len() == 2
-        && card2amount.iter()
-        .map(|(_k, v)| { if v == &4 { 1 } else { 0 } }).sum::<u32>() > 0 {
-        HandType::FourOfAKind
-    } else if card2amount.len() == 2 {
-        HandType::FullHouse
-    } else if card2amount.iter()
-        .map(|(_k, v)| if v == &3 { 1 } else { 0 }).sum::<u32>() > 0 {
-        HandType::ThreeOfAKind
-    } else if card2amount.iter().map(|(_k, v)| if v == &2 { 1 } else { 0 }).sum::<u32>() > 1 {
-        HandType::TwoPair
-    } else if card2amount.iter().map(|(_k, v)| if v == &2 { 1 } else { 0 }).sum::<u32>() > 0 {
-        HandType::OnePair
-    } else {
-        HandType::HighCard
-    }
+    let mut multiplicities: Vec<usize> = card2amount.into_values().collect();
+    multiplicities.sort_unstable_by(|l, r| r.cmp(l));
+    multiplicities
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Clone)]
+/* Declared weakest first, so the derived ordering ranks stronger types higher */
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Debug)]
 enum HandType {
-    FiveOfAKind,
-    FourOfAKind,
-    FullHouse,
-    ThreeOfAKind,
-    TwoPair,
-    OnePair,
     HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
 }
 
+impl HandType {
+    /* Expects the multiplicities sorted largest first, works for hands of any length */
+    fn from_multiplicities(multiplicities: &[usize]) -> HandType {
+        let largest = multiplicities.first().copied().unwrap_or(0);
+        let second = multiplicities.get(1).copied().unwrap_or(0);
+
+        match (largest, second) {
+            (l, _) if l >= 5 => HandType::FiveOfAKind,
+            (4, _) => HandType::FourOfAKind,
+            (3, s) if s >= 2 => HandType::FullHouse,
+            (3, _) => HandType::ThreeOfAKind,
+            (2, 2) => HandType::TwoPair,
+            (2, _) => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
+    }
+}
+
+/* Declared weakest first, so the derived ordering ranks stronger cards higher */
 #[derive(PartialOrd, Ord, PartialEq, Eq, Clone)]
 enum Card {
-    Ace,
-    King,
-    Queen,
-    Ten,
-    Nine,
-    Eight,
-    Seven,
-    Six,
-    Five,
-    Four,
-    Three,
-    Two,
     Joker,
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Queen,
+    King,
+    Ace,
+}
+
+impl Card {
+    fn from_char(c: char) -> Card {
+        match c {
+            'A' => Card::Ace,
+            'K' => Card::King,
+            'Q' => Card::Queen,
+            'J' => Card::Joker,
+            'T' => Card::Ten,
+            '9' => Card::Nine,
+            '8' => Card::Eight,
+            '7' => Card::Seven,
+            '6' => Card::Six,
+            '5' => Card::Five,
+            '4' => Card::Four,
+            '3' => Card::Three,
+            '2' => Card::Two,
+            _ => panic!("unexpected input")
+        }
+    }
+
+    fn to_char(&self) -> char {
+        match self {
+            Card::Ace => 'A',
+            Card::King => 'K',
+            Card::Queen => 'Q',
+            Card::Joker => 'J',
+            Card::Ten => 'T',
+            Card::Nine => '9',
+            Card::Eight => '8',
+            Card::Seven => '7',
+            Card::Six => '6',
+            Card::Five => '5',
+            Card::Four => '4',
+            Card::Three => '3',
+            Card::Two => '2',
+        }
+    }
 }
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
     let players: Vec<Player> =
         BufReader::new(file_in).lines()
@@ -173,26 +273,9 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
             .map(|x| {
                 let mut it = x.split_whitespace();
                 let cards: Vec<Card> =
-                    it.next().unwrap().chars().map(|c|
-                        match c {
-                            'A' => Card::Ace,
-                            'K' => Card::King,
-                            'Q' => Card::Queen,
-                            'J' => Card::Joker,
-                            'T' => Card::Ten,
-                            '9' => Card::Nine,
-                            '8' => Card::Eight,
-                            '7' => Card::Seven,
-                            '6' => Card::Six,
-                            '5' => Card::Five,
-                            '4' => Card::Four,
-                            '3' => Card::Three,
-                            '2' => Card::Two,
-                            _ => panic!("unexpected input")
-                        }
-                    ).collect();
+                    it.next().unwrap().chars().map(Card::from_char).collect();
                 let bet = it.next().unwrap().parse::<usize>().unwrap();
-                let best_hand = get_best_type(cards.clone());
+                let best_hand = get_best_type(&cards);
                 Player {
                     hand: Hand {
                         cards,
@@ -205,4 +288,87 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
     Ok(Problem {
         players
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_CARDS: [Card; 13] = [
+        Card::Joker, Card::Two, Card::Three, Card::Four, Card::Five, Card::Six, Card::Seven,
+        Card::Eight, Card::Nine, Card::Ten, Card::Queen, Card::King, Card::Ace,
+    ];
+
+    /* Every hand of the given length, in lexicographic order */
+    fn all_hands(length: u32) -> impl Iterator<Item = Vec<Card>> {
+        (0..13usize.pow(length)).map(move |mut index| {
+            (0..length).map(|_| {
+                let card = ALL_CARDS[index % 13].clone();
+                index /= 13;
+                card
+            }).collect()
+        })
+    }
+
+    fn describe(cards: &[Card]) -> String {
+        cards.iter().map(|card| card.to_char()).collect()
+    }
+
+    #[test]
+    fn best_type_matches_exhaustive_substitution_for_every_hand() {
+        for cards in all_hands(5) {
+            assert_eq!(get_best_type(&cards),
+                       get_best_type_exhaustive(cards.clone()),
+                       "{}", describe(&cards));
+        }
+    }
+
+    fn hand(cards: Vec<Card>) -> Hand {
+        Hand { best_hand: get_best_type(&cards), cards }
+    }
+
+    fn assert_total_order<T: Ord>(samples: &[T]) {
+        for (i, a) in samples.iter().enumerate() {
+            assert_eq!(a.cmp(a), Ordering::Equal, "not reflexive for sample {}", i);
+            for (j, b) in samples.iter().enumerate() {
+                assert_eq!(a.cmp(b), b.cmp(a).reverse(), "not antisymmetric for {} and {}", i, j);
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b, "Equal is not == for {} and {}", i, j);
+                for (k, c) in samples.iter().enumerate() {
+                    if a <= b && b <= c {
+                        assert!(a <= c, "not transitive for {}, {} and {}", i, j, k);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hands_are_totally_ordered() {
+        // Every 13th hand of three cards, padded with a two and a joker
+        let hands: Vec<Hand> = all_hands(3).map(|mut cards| {
+            cards.extend([Card::Two, Card::Joker]);
+            hand(cards)
+        }).step_by(13).collect();
+        assert_total_order(&hands);
+    }
+
+    #[test]
+    fn players_are_totally_ordered() {
+        let players: Vec<Player> = ["JJ22A", "22J2A", "2222A", "AAAAJ", "JJJJJ", "KQJT9", "KQ2T9"]
+            .iter()
+            .flat_map(|cards| (1..4).map(move |bet| Player {
+                hand: hand(cards.chars().map(Card::from_char).collect()),
+                bet,
+            }))
+            .collect();
+        assert_total_order(&players);
+    }
+
+    #[test]
+    fn best_type_matches_exhaustive_substitution_for_longer_hands() {
+        let hands = ["JJ2233A", "J223344", "JJJ2345", "2233445", "J2345678", "JJ234567", "22JJ33J", "AAKKQQJJ"];
+        for hand in hands {
+            let cards: Vec<Card> = hand.chars().map(Card::from_char).collect();
+            assert_eq!(get_best_type(&cards), get_best_type_exhaustive(cards.clone()), "{}", hand);
+        }
+    }
+}
@@ -1,7 +1,9 @@
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
 use std::env;
+use std::cmp::Ordering;
 use std::collections::BTreeMap ;
+use std::fmt;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -9,17 +11,71 @@ fn main() -> io::Result<()> {
 
     let problem = read_input(input)?;
 
+    if args.iter().any(|arg| arg == "--table") {
+        print_ranking(&problem);
+    }
+
     println!("{:?} are the total winnings", solve1(&problem));
 
+    let rules =
+        if args.iter().any(|arg| arg == "--jokers") { Rules::Jokers } else { Rules::Standard };
+
+    if args.iter().any(|arg| arg == "--stats") {
+        print_statistics(&problem);
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--versus") {
+        let left = Hand::parse(&args[i + 1]);
+        let right = Hand::parse(&args[i + 2]);
+        println!("{}", explain_versus(&left, &right, rules));
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--equity") {
+        let hand = Hand::parse(&args[i + 1]);
+        let opponents = args[i + 2].parse::<usize>().unwrap();
+        let trials = flag_value(&args, "--trials").unwrap_or(100000);
+        let seed = flag_value(&args, "--seed").unwrap_or(2023) as u64;
+        let mut rng = Rng::new(seed);
+        println!("{} has an equity of {:.4} against {} random opponents ({:?} rules, {} trials, seed {})",
+                 hand,
+                 equity(&hand, opponents, trials, rules, &mut rng),
+                 opponents,
+                 rules,
+                 trials,
+                 seed);
+    }
+
     Ok(())
 }
+
 fn solve1(problem: &Problem) -> usize {
-    let mut players = problem.players.clone();
+    ranking(problem).iter().map(|(rank, player)| rank * player.bet).sum()
+}
+
+/* Players ordered from weakest to strongest hand, paired with their (1-based) rank */
+fn ranking(problem: &Problem) -> Vec<(usize, &Player)> {
+    let mut players: Vec<&Player> = problem.players.iter().collect();
     players.sort();
 
-    players.iter().rev().enumerate().map(|(i, player)| {
-            (i+1) * player.bet
-    }).sum()
+    players.into_iter().enumerate().map(|(i, player)| (i + 1, player)).collect()
+}
+
+fn print_ranking(problem: &Problem) {
+    println!("{:>5} {:>5} {:<12} {:>5} {:>10}", "rank", "hand", "type", "bid", "winnings");
+    for (rank, player) in ranking(problem) {
+        println!("{:>5} {:>5} {:<12} {:>5} {:>10}",
+                 rank,
+                 player.hand.to_string(),
+                 format!("{:?}", player.hand.get_type()),
+                 player.bet,
+                 rank * player.bet);
+    }
+}
+
+fn flag_value(args: &[String], flag: &str) -> Option<usize> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .map(|i| args[i + 1].parse::<usize>().unwrap())
 }
 
 #[derive(Clone)]
@@ -27,108 +83,386 @@ struct Problem {
     players: Vec<Player>
 }
 
-#[derive(Clone, PartialEq, Eq, Ord)]
+/* Players are ordered by hand first, ties between identical hands are broken on the bet */
+#[derive(Clone, PartialEq, Eq)]
 struct Player{
     hand: Hand,
     bet: usize
 }
 
+impl Ord for Player {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.hand.cmp(&other.hand)
+            .then_with(|| self.bet.cmp(&other.bet))
+    }
+}
+
 impl PartialOrd for Player {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        self.hand.partial_cmp(&other.hand)
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
-#[derive(Clone, Eq, Ord)]
+/* Hands are ordered by type first and then card by card, so stronger hands compare greater.
+   The types under both rules are derived from the cards once, when the hand is made. */
+#[derive(Clone, PartialEq, Eq)]
 struct Hand {
-    cards: Vec<Card>
+    cards: Vec<Card>,
+    standard_type: HandType,
+    joker_type: HandType,
 }
 
-impl PartialEq for Hand {
-    fn eq(&self, other: &Self) -> bool {
-        // Define how two structs are considered equal
-        self.cards == other.cards // Example: compare based on `field1`
+impl Ord for Hand {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.cmp_with(other, Rules::Standard)
     }
 }
 
 impl PartialOrd for Hand {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        // Define how two structs are ordered
-        let ordering = self.get_type().partial_cmp(&other.get_type()); // Example: compare based on `field1`
-        if ordering.is_some() && ordering.unwrap() == std::cmp::Ordering::Equal {
-            for i in 0..(self.cards.len()) {
-                let l = self.cards.get(i).unwrap();
-                let r = other.cards.get(i).unwrap();
-                let o = l.cmp(r);
-                if o != std::cmp::Ordering::Equal {
-                    return Some(o)
-                }
-            }
-            ordering
-        } else {
-            ordering
-        }
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for Hand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let cards: String = self.cards.iter().map(|card| card.to_char()).collect();
+        write!(f, "{}", cards)
     }
 }
 
 impl Hand {
+    fn new(cards: Vec<Card>) -> Hand {
+        Hand {
+            standard_type: classify(&cards, Rules::Standard),
+            joker_type: classify(&cards, Rules::Jokers),
+            cards,
+        }
+    }
+
+    fn parse(cards: &str) -> Hand {
+        Hand::new(cards.chars().map(Card::from_char).collect())
+    }
+
     fn get_type(&self) -> HandType {
-        let mut card2amount: BTreeMap<Card, u32> = BTreeMap::new();
+        self.get_type_with(Rules::Standard)
+    }
 
-        for c in &self.cards {
-            *card2amount.entry(c.clone()).or_insert(0) += 1;
+    fn get_type_with(&self, rules: Rules) -> HandType {
+        match rules {
+            Rules::Standard => self.standard_type,
+            Rules::Jokers => self.joker_type,
         }
+    }
+
+    fn cmp_with(&self, other: &Hand, rules: Rules) -> Ordering {
+        self.get_type_with(rules).cmp(&other.get_type_with(rules))
+            .then_with(|| self.first_difference(other, rules)
+                              .map_or(Ordering::Equal, |(_i, ordering)| ordering))
+    }
+
+    /* The position of the first card that breaks a tie between two hands of the same type */
+    fn first_difference(&self, other: &Hand, rules: Rules) -> Option<(usize, Ordering)> {
+        self.cards.iter()
+            .zip(other.cards.iter())
+            .map(|(l, r)| l.strength(rules).cmp(&r.strength(rules)))
+            .enumerate()
+            .find(|(_i, ordering)| ordering != &Ordering::Equal)
+    }
+}
 
-        if card2amount.len() == 1 {
-            HandType::FiveOfAKind
-        } else if card2amount.len() == 2
-            && card2amount.iter()
-            .map(|(_k, v)| { if v == &4 { 1 } else { 0 } } ).sum::<u32>() > 0 {
-            HandType::FourOfAKind
-        } else if card2amount.len() == 2 {
-            HandType::FullHouse
-        } else if card2amount.iter()
-            .map(|(_k, v)| if v == &3 { 1 } else { 0 }).sum::<u32>() > 0 {
-            HandType::ThreeOfAKind
-        } else if card2amount.iter().map(|(_k, v)| if v == &2 { 1 } else { 0 }).sum::<u32>() > 1 {
-            HandType::TwoPair
-        } else if card2amount.iter().map(|(_k, v)| if v == &2 { 1 } else { 0 }).sum::<u32>() > 0 {
-            HandType::OnePair
+/* The type of the cards, with jacks wild under the joker rules */
+fn classify(cards: &[Card], rules: Rules) -> HandType {
+    let mut card2amount: BTreeMap<&Card, usize> = BTreeMap::new();
+    let mut jokers = 0;
+
+    for c in cards {
+        if rules == Rules::Jokers && c == &Card::Jack {
+            jokers += 1;
         } else {
-            HandType::HighCard
+            *card2amount.entry(c).or_insert(0) += 1;
         }
     }
+
+    let mut multiplicities: Vec<usize> = card2amount.into_values().collect();
+    multiplicities.sort_unstable_by(|l, r| r.cmp(l));
+
+    // A joker always does best by copying the card we already hold most of
+    match multiplicities.first_mut() {
+        Some(largest) => *largest += jokers,
+        None => multiplicities.push(jokers),
+    }
+
+    HandType::from_multiplicities(&multiplicities)
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Clone)]
+/* Declared weakest first, so the derived ordering ranks stronger types higher */
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy, Debug)]
 enum HandType {
-    FiveOfAKind,
-    FourOfAKind,
-    FullHouse,
-    ThreeOfAKind,
-    TwoPair,
-    OnePair,
     HighCard,
+    OnePair,
+    TwoPair,
+    ThreeOfAKind,
+    FullHouse,
+    FourOfAKind,
+    FiveOfAKind,
+}
+
+impl HandType {
+    /* Expects the multiplicities sorted largest first, works for hands of any length */
+    fn from_multiplicities(multiplicities: &[usize]) -> HandType {
+        let largest = multiplicities.first().copied().unwrap_or(0);
+        let second = multiplicities.get(1).copied().unwrap_or(0);
+
+        match (largest, second) {
+            (l, _) if l >= 5 => HandType::FiveOfAKind,
+            (4, _) => HandType::FourOfAKind,
+            (3, s) if s >= 2 => HandType::FullHouse,
+            (3, _) => HandType::ThreeOfAKind,
+            (2, 2) => HandType::TwoPair,
+            (2, _) => HandType::OnePair,
+            _ => HandType::HighCard,
+        }
+    }
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Clone)]
+/* Declared weakest first, so the derived ordering ranks stronger cards higher */
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Copy)]
 enum Card {
-    Ace,
-    King,
-    Queen,
-    Jack,
-    Ten,
-    Nine,
-    Eight,
-    Seven,
-    Six,
-    Five,
-    Four,
-    Three,
     Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+}
+
+const ALL_CARDS: [Card; 13] = [
+    Card::Two,
+    Card::Three,
+    Card::Four,
+    Card::Five,
+    Card::Six,
+    Card::Seven,
+    Card::Eight,
+    Card::Nine,
+    Card::Ten,
+    Card::Jack,
+    Card::Queen,
+    Card::King,
+    Card::Ace,
+];
+
+/* Part 1 plays with jacks, part 2 turns them into jokers that are wild but the weakest card */
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Rules {
+    Standard,
+    Jokers,
+}
+
+impl Card {
+    fn strength(self, rules: Rules) -> usize {
+        match (rules, self) {
+            (Rules::Jokers, Card::Jack) => 0,
+            _ => self as usize + 1,
+        }
+    }
+
+    fn from_char(c: char) -> Card {
+        match c {
+            'A' => Card::Ace,
+            'K' => Card::King,
+            'Q' => Card::Queen,
+            'J' => Card::Jack,
+            'T' => Card::Ten,
+            '9' => Card::Nine,
+            '8' => Card::Eight,
+            '7' => Card::Seven,
+            '6' => Card::Six,
+            '5' => Card::Five,
+            '4' => Card::Four,
+            '3' => Card::Three,
+            '2' => Card::Two,
+            _ => panic!("unexpected input")
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Card::Ace => 'A',
+            Card::King => 'K',
+            Card::Queen => 'Q',
+            Card::Jack => 'J',
+            Card::Ten => 'T',
+            Card::Nine => '9',
+            Card::Eight => '8',
+            Card::Seven => '7',
+            Card::Six => '6',
+            Card::Five => '5',
+            Card::Four => '4',
+            Card::Three => '3',
+            Card::Two => '2',
+        }
+    }
+}
+
+/* Deals the random hands for --equity, the same deals every time for a given --seed */
+struct Rng {
+    state: u64
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+fn random_hand(rng: &mut Rng) -> Hand {
+    Hand::new((0..5).map(|_| ALL_CARDS[rng.below(ALL_CARDS.len())]).collect())
 }
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+/* Every possible hand of five cards, each card drawn independently from the 13 ranks */
+fn all_hands() -> impl Iterator<Item = Hand> {
+    let total = ALL_CARDS.len().pow(5);
+    (0..total).map(|mut index| {
+        let mut cards = Vec::with_capacity(5);
+        for _ in 0..5 {
+            cards.push(ALL_CARDS[index % ALL_CARDS.len()]);
+            index /= ALL_CARDS.len();
+        }
+        Hand::new(cards)
+    })
+}
+
+fn type_distribution(rules: Rules) -> BTreeMap<HandType, f64> {
+    let mut type2amount: BTreeMap<HandType, usize> = BTreeMap::new();
+    let mut total = 0;
+
+    for hand in all_hands() {
+        *type2amount.entry(hand.get_type_with(rules)).or_insert(0) += 1;
+        total += 1;
+    }
+
+    type2amount.into_iter()
+               .map(|(hand_type, amount)| (hand_type, amount as f64 / total as f64))
+               .collect()
+}
+
+/* Expected total winnings when every bid is dealt a random hand.
+   Two random hands only compare equal when they hold exactly the same cards, in which case
+   the bid breaks the tie. Since that chance is the same under both rule sets, so is the answer. */
+fn expected_winnings(bets: &[usize]) -> f64 {
+    let p_equal = 1.0 / ALL_CARDS.len().pow(5) as f64;
+    let p_lower = (1.0 - p_equal) / 2.0;
+
+    bets.iter().enumerate().map(|(i, bet)| {
+        let expected_rank: f64 = 1.0 + bets.iter().enumerate()
+            .filter(|(j, _other)| j != &i)
+            .map(|(_j, other)| {
+                let tie_break =
+                    match other.cmp(bet) {
+                        Ordering::Less => 1.0,
+                        Ordering::Equal => 0.5,
+                        Ordering::Greater => 0.0,
+                    };
+                p_lower + p_equal * tie_break
+            })
+            .sum::<f64>();
+        expected_rank * *bet as f64
+    }).sum()
+}
+
+/* Share of the pot the hand wins against random opponents, ties split the pot evenly */
+fn equity(hand: &Hand, opponents: usize, trials: usize, rules: Rules, rng: &mut Rng) -> f64 {
+    let mut won = 0.0;
+
+    for _ in 0..trials {
+        let mut ties = 0;
+        let mut lost = false;
+
+        for _ in 0..opponents {
+            match hand.cmp_with(&random_hand(rng), rules) {
+                Ordering::Less => {
+                    lost = true;
+                    break;
+                },
+                Ordering::Equal => ties += 1,
+                Ordering::Greater => {},
+            }
+        }
+
+        if !lost {
+            won += 1.0 / (ties + 1) as f64;
+        }
+    }
+
+    won / trials as f64
+}
+
+fn explain_versus(left: &Hand, right: &Hand, rules: Rules) -> String {
+    let left_type = left.get_type_with(rules);
+    let right_type = right.get_type_with(rules);
+
+    if left_type != right_type {
+        let (winner, winner_type, loser, loser_type) =
+            if left_type > right_type {
+                (left, left_type, right, right_type)
+            } else {
+                (right, right_type, left, left_type)
+            };
+        return format!("{} ({:?}) beats {} ({:?}) on hand type",
+                       winner, winner_type, loser, loser_type);
+    }
+
+    match left.first_difference(right, rules) {
+        None => format!("{} and {} are both {:?} with identical cards, it's a tie",
+                        left, right, left_type),
+        Some((i, ordering)) => {
+            let (winner, loser) =
+                if ordering == Ordering::Greater { (left, right) } else { (right, left) };
+            format!("{} beats {}, both are {:?}; decided by card {}: {} beats {}",
+                    winner,
+                    loser,
+                    left_type,
+                    i + 1,
+                    winner.cards[i].to_char(),
+                    loser.cards[i].to_char())
+        }
+    }
+}
+
+fn print_statistics(problem: &Problem) {
+    for rules in [Rules::Standard, Rules::Jokers] {
+        println!("Hand type distribution with {:?} rules:", rules);
+        for (hand_type, probability) in type_distribution(rules) {
+            println!("\t{:<12} {:.6}", format!("{:?}", hand_type), probability);
+        }
+    }
+
+    let bets: Vec<usize> = problem.players.iter().map(|player| player.bet).collect();
+    println!("{:.2} are the expected winnings for this bid table with random hands",
+             expected_winnings(&bets));
+}
+
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
     let players: Vec<Player> =
         BufReader::new(file_in).lines()
@@ -136,29 +470,10 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
             .map(|x| {
                 let mut it = x.split_whitespace();
                 let cards: Vec<Card> =
-                    it.next().unwrap().chars().map(|c|
-                        match c {
-                            'A' => Card::Ace,
-                            'K' => Card::King,
-                            'Q' => Card::Queen,
-                            'J' => Card::Jack,
-                            'T' => Card::Ten,
-                            '9' => Card::Nine,
-                            '8' => Card::Eight,
-                            '7' => Card::Seven,
-                            '6' => Card::Six,
-                            '5' => Card::Five,
-                            '4' => Card::Four,
-                            '3' => Card::Three,
-                            '2' => Card::Two,
-                            _ => panic!("unexpected input")
-                        }
-                    ).collect();
+                    it.next().unwrap().chars().map(Card::from_char).collect();
                 let bet = it.next().unwrap().parse::<usize>().unwrap();
                 Player {
-                    hand: Hand {
-                        cards
-                    },
+                    hand: Hand::new(cards),
                     bet
                 }
             })
@@ -166,4 +481,54 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
     Ok(Problem {
         players
     })
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Hands of only a few ranks, some of them twice, so equal types and identical hands come up often */
+    fn sample_hands() -> Vec<Hand> {
+        let ranks = [Card::Two, Card::Jack, Card::Ace];
+        (0..243).step_by(3)
+                .chain((0..243).step_by(5))
+                .map(|mut index: usize| Hand::new((0..5).map(|_| {
+                    let card = ranks[index % ranks.len()];
+                    index /= ranks.len();
+                    card
+                }).collect()))
+                .collect()
+    }
+
+    fn assert_total_order<T>(samples: &[T],
+                             cmp: impl Fn(&T, &T) -> Ordering,
+                             eq: impl Fn(&T, &T) -> bool) {
+        for (i, a) in samples.iter().enumerate() {
+            assert_eq!(cmp(a, a), Ordering::Equal, "not reflexive for sample {}", i);
+            for (j, b) in samples.iter().enumerate() {
+                assert_eq!(cmp(a, b), cmp(b, a).reverse(), "not antisymmetric for {} and {}", i, j);
+                assert_eq!(cmp(a, b) == Ordering::Equal, eq(a, b), "Equal is not == for {} and {}", i, j);
+                for (k, c) in samples.iter().enumerate() {
+                    if cmp(a, b) != Ordering::Greater && cmp(b, c) != Ordering::Greater {
+                        assert_ne!(cmp(a, c), Ordering::Greater, "not transitive for {}, {} and {}", i, j, k);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hands_are_totally_ordered_under_both_rules() {
+        let hands = sample_hands();
+        assert_total_order(&hands, |a, b| a.cmp(b), |a, b| a == b);
+        assert_total_order(&hands, |a, b| a.cmp_with(b, Rules::Jokers), |a, b| a == b);
+    }
+
+    #[test]
+    fn players_are_totally_ordered() {
+        let players: Vec<Player> = sample_hands().into_iter()
+                                                 .enumerate()
+                                                 .map(|(i, hand)| Player { hand, bet: i % 3 })
+                                                 .collect();
+        assert_total_order(&players, |a, b| a.cmp(b), |a, b| a == b);
+    }
+}
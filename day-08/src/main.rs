@@ -1,177 +1,518 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::File;
-use std::io::{self, BufReader, BufRead};
+use std::io::{self, BufReader, BufRead, BufWriter, Write};
 use std::env;
+use std::fmt;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let input = &args[1];
 
+    let selector = |flag: &str, default: &str| {
+        let selector = args.iter()
+                           .position(|arg| arg == flag)
+                           .map_or(default, |i| args[i + 1].as_str());
+        NodeSelector::parse(selector)
+    };
+
     let problem = read_input(input)?;
-    println!("{:?} steps are required to reach ZZZ", solve1(&problem));
 
-    println!("{:?} steps does it take before you're only on nodes that end with Z", solve2(&problem));
+    let end_selector = selector("--end", "ZZZ");
+    let starts = problem.select(&selector("--start", "AAA"));
+    let ends = problem.select(&end_selector);
+    match solve1(&problem, &starts, &ends) {
+        Arrival::At(step) => println!("{:?} steps are required to reach {}", step, end_selector),
+        Arrival::Never => println!("{} can never be reached", end_selector),
+    }
+
+    let ghost_end_selector = selector("--ghost-end", "??Z");
+    let ghost_starts = problem.select(&selector("--ghost-start", "??A"));
+    let ghost_ends = problem.select(&ghost_end_selector);
+
+    if args.iter().any(|arg| arg == "--cycles") {
+        for cycle in ghost_cycles(&problem, &ghost_starts, &ghost_ends) {
+            println!("{} enters a cycle of {} steps after {} steps, ends at {:?} before and at offsets {:?} within the cycle",
+                     problem.names[cycle.start],
+                     cycle.cycle_length,
+                     cycle.prefix_length,
+                     cycle.prefix_hits,
+                     cycle.cycle_offsets);
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--dot") {
+        let cycles = ghost_cycles(&problem, &ghost_starts, &ghost_ends);
+        let mut out = BufWriter::new(File::create(&args[i + 1])?);
+        write_dot(&problem, &ghost_starts, &ghost_ends, &cycles, &mut out)?;
+    }
+
+    if args.iter().any(|arg| arg == "--components") {
+        for (i, component) in problem.components().iter().enumerate() {
+            let names = |ids: &[usize]| -> Vec<&str> {
+                ids.iter()
+                   .filter(|id| component.contains(id))
+                   .map(|id| problem.names[*id].as_str())
+                   .collect()
+            };
+            println!("Component {} has {} nodes, starts {:?} and ends {:?}",
+                     i,
+                     component.len(),
+                     names(&ghost_starts),
+                     names(&ghost_ends));
+        }
+    }
+
+    match solve2(&problem, &ghost_starts, &ghost_ends) {
+        Arrival::At(step) =>
+            println!("{:?} steps does it take before you're only on {}", step, ghost_end_selector),
+        Arrival::Never => println!("The ghosts are never all on {} at once", ghost_end_selector),
+    }
     Ok(())
 }
 
-fn solve1(problem: &Problem) -> usize {
+/* Fewest steps for any of the start nodes to reach an end node */
+fn solve1(problem: &Problem, starts: &[usize], ends: &[usize]) -> Arrival {
+    let reachable = problem.reachable_from(starts);
+    if !ends.iter().any(|end| reachable[*end]) {
+        return Arrival::Never
+    }
+
+    starts.iter()
+          .map(|start| analyse_ghost(problem, *start, ends))
+          .filter_map(|cycle| cycle.first_end())
+          .min()
+          .map_or(Arrival::Never, |step| Arrival::At(step as u128))
+}
+
+fn solve2(problem: &Problem, starts: &[usize], ends: &[usize]) -> Arrival {
+    let cycles = ghost_cycles(problem, starts, ends);
+
+    if cycles.iter().any(|cycle| cycle.first_end().is_none()) {
+        return Arrival::Never
+    }
+
+    earliest_common_arrival(&cycles)
+}
+
+fn ghost_cycles(problem: &Problem, starts: &[usize], ends: &[usize]) -> Vec<GhostCycle> {
+    starts.iter()
+          .map(|start| analyse_ghost(problem, *start, ends))
+          .collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Arrival {
+    At(u128),
+    Never,
+}
+
+/* The walk of a single ghost: after prefix_length steps it enters a loop of cycle_length steps.
+   prefix_hits are the steps before the loop on which it stands on an end node, cycle_offsets
+   the positions within the loop (relative to prefix_length) on which it does. */
+#[derive(Clone, Debug)]
+struct GhostCycle {
+    start: usize,
+    prefix_length: usize,
+    cycle_length: usize,
+    prefix_hits: Vec<usize>,
+    cycle_offsets: Vec<usize>,
+}
+
+impl GhostCycle {
+    fn is_end_at(&self, step: u128) -> bool {
+        if step < self.prefix_length as u128 {
+            self.prefix_hits.contains(&(step as usize))
+        } else {
+            let offset = (step - self.prefix_length as u128) % self.cycle_length as u128;
+            self.cycle_offsets.contains(&(offset as usize))
+        }
+    }
+
+    fn first_end(&self) -> Option<usize> {
+        self.prefix_hits.first().copied()
+            .or(self.cycle_offsets.first().map(|offset| self.prefix_length + offset))
+    }
+}
+
+/* Walks until a (node, instruction_index) state repeats, which is bounded by nodes * instructions */
+fn analyse_ghost(problem: &Problem, start: usize, ends: &[usize]) -> GhostCycle {
+    let instruction_count = problem.instructions.len();
+
+    let mut is_end = vec![false; problem.names.len()];
+    for end in ends {
+        is_end[*end] = true;
+    }
+
+    let mut first_seen: Vec<Option<usize>> = vec![None; problem.names.len() * instruction_count];
+
+    let mut end_steps: Vec<usize> = Vec::new();
+
     let mut step: usize = 0;
 
-    let mut position = String::from("AAA");
+    let mut position = start;
 
-    while position != String::from("ZZZ") {
+    loop {
+        let instruction_index = step % instruction_count;
+        let state = position * instruction_count + instruction_index;
+
+        if let Some(prefix_length) = first_seen[state] {
+            let (prefix_hits, cycle_hits): (Vec<usize>, Vec<usize>) =
+                end_steps.into_iter().partition(|end_step| end_step < &prefix_length);
+
+            return GhostCycle {
+                start,
+                prefix_length,
+                cycle_length: step - prefix_length,
+                prefix_hits,
+                cycle_offsets: cycle_hits.into_iter().map(|hit| hit - prefix_length).collect(),
+            }
+        }
 
-        let instruction =
-            problem.instructions.get(step % problem.instructions.len()).unwrap();
+        first_seen[state] = Some(step);
 
-        let option =
-            problem.options.get(&position).unwrap();
+        if is_end[position] {
+            end_steps.push(step)
+        }
 
-        position =
-            if instruction == &'L' {
-                option.0.clone()
-            } else {
-                option.1.clone()
-            };
+        position = problem.next(position, problem.instructions[instruction_index]);
 
         step += 1
     }
-    step
 }
 
-fn solve2(problem: &Problem) -> usize {
+/* The (node, instruction) transitions a ghost keeps repeating once it has entered its cycle */
+fn cycle_edges(problem: &Problem, cycle: &GhostCycle) -> HashSet<(usize, Instruction)> {
+    let instruction_at = |step: usize| problem.instructions[step % problem.instructions.len()];
 
-    /* Not going to lie, I did have to look this Least Common Multiple shit up */
-    fn gcd(a: usize, b: usize) -> usize {
-        if b == 0 {
-            a
-        } else {
-            gcd(b, a % b)
-        }
+    let mut position = cycle.start;
+    for step in 0..cycle.prefix_length {
+        position = problem.next(position, instruction_at(step));
     }
 
-    fn lcm(a: usize, b: usize) -> usize {
-        a / gcd(a, b) * b
+    let mut edges = HashSet::new();
+    for step in cycle.prefix_length..(cycle.prefix_length + cycle.cycle_length) {
+        let instruction = instruction_at(step);
+        edges.insert((position, instruction));
+        position = problem.next(position, instruction);
     }
+    edges
+}
 
-    fn lcm_of_values(arr: Vec<usize>) -> usize {
-        arr.iter().fold(1, |acc, &num| lcm(acc, num))
+/* Graphviz export of the network: starts are green, ends red, and every ghost's cycle is
+   drawn in its own colour. */
+fn write_dot(problem: &Problem,
+             starts: &[usize],
+             ends: &[usize],
+             cycles: &[GhostCycle],
+             out: &mut impl Write) -> io::Result<()> {
+    const PALETTE: [&str; 8] =
+        ["blue", "darkorange", "purple", "deeppink", "darkgreen", "brown", "cyan4", "gold3"];
+
+    let cycle_edges: Vec<HashSet<(usize, Instruction)>> =
+        cycles.iter().map(|cycle| cycle_edges(problem, cycle)).collect();
+
+    writeln!(out, "digraph network {{")?;
+    writeln!(out, "    node [shape=circle, fontsize=10];")?;
+
+    for (id, name) in problem.names.iter().enumerate() {
+        let style =
+            if starts.contains(&id) {
+                ", style=filled, fillcolor=palegreen"
+            } else if ends.contains(&id) {
+                ", style=filled, fillcolor=salmon"
+            } else {
+                ""
+            };
+        writeln!(out, "    n{} [label=\"{}\"{}];", id, name, style)?;
     }
 
-    let positions: HashSet<String> =
-        HashSet::from_iter(problem.options.keys()
-                                              .filter(|x| {x.chars().nth(2).unwrap() == 'A'})
-                                              .map(|x|x.clone()));
+    for (id, (left, right)) in problem.options.iter().enumerate() {
+        let mut edges = vec![(*left, Instruction::Left, "L")];
+        if left == right {
+            edges[0].2 = "LR";
+        } else {
+            edges.push((*right, Instruction::Right, "R"));
+        }
 
-    /*for position in positions {
-        println!("Trying to find the possible end_states for {:?}", position);
-        for pes  in find_possible_end_states(problem, position) {
-            println!("\tFound {:?} ..", pes)
+        for (target, instruction, label) in edges {
+            let ghost = cycle_edges.iter().position(|edges| {
+                edges.contains(&(id, instruction))
+                    || (label == "LR" && edges.contains(&(id, Instruction::Right)))
+            });
+            let style = match ghost {
+                Some(ghost) => format!(", color={}, penwidth=3", PALETTE[ghost % PALETTE.len()]),
+                None => String::new(),
+            };
+            writeln!(out, "    n{} -> n{} [label=\"{}\"{}];", id, target, label, style)?;
         }
-    }*/
+    }
 
-    // This won't work if I return multiple state, but fuck it.
-    let values: Vec<usize> =
-        positions.iter()
-                 .map(|position|
-                    find_possible_end_states(problem,
-                                             position.clone())).into_iter()
-                                                                         .map(|x|x.iter().next().unwrap().1)
-                                                                         .collect();
-    lcm_of_values(values)
+    writeln!(out, "}}")
 }
 
-fn find_possible_end_states(problem: &Problem,
-                            begin_state: String) -> Vec<(String, usize)>  {
+/* Earliest step on which every ghost stands on an end node at the same time */
+fn earliest_common_arrival(cycles: &[GhostCycle]) -> Arrival {
+    if cycles.is_empty() {
+        return Arrival::Never
+    }
 
-    #[derive(Clone, Debug, Eq, PartialEq, Hash)]
-    struct State {
-        position: String,
-        instruction_index: usize
+    // Before the longest prefix has passed, a common arrival has to be one of that ghost's prefix hits
+    let longest = cycles.iter().max_by_key(|cycle| cycle.prefix_length).unwrap();
+    let settled = longest.prefix_length as u128;
+
+    if let Some(step) = longest.prefix_hits.iter()
+                               .map(|hit| *hit as u128)
+                               .find(|hit| cycles.iter().all(|cycle| cycle.is_end_at(*hit))) {
+        return Arrival::At(step)
     }
 
-    let end_positions: HashSet<String> =
-        HashSet::from_iter(problem.options.keys()
-            .filter(|x| {x.chars().nth(2).unwrap() == 'Z'})
-            .map(|x|x.clone()));
+    // From then on every ghost is periodic, so combine their hit residues with the CRT
+    let mut congruences: Vec<(u128, u128)> = vec![(0, 1)];
+
+    for cycle in cycles {
+        let modulus = cycle.cycle_length as u128;
+        let mut next: Vec<(u128, u128)> =
+            congruences.iter()
+                       .flat_map(|congruence| cycle.cycle_offsets.iter().filter_map(move |offset| {
+                           let residue = (cycle.prefix_length + offset) as u128 % modulus;
+                           combine_congruences(*congruence, (residue, modulus))
+                       }))
+                       .collect();
+        next.sort();
+        next.dedup();
+        congruences = next;
+    }
 
-    let mut seen_states : HashSet<State> = HashSet::new();
+    congruences.iter()
+               .map(|(residue, modulus)| {
+                   if residue >= &settled {
+                       *residue
+                   } else {
+                       residue + (settled - residue).div_ceil(*modulus) * modulus
+                   }
+               })
+               .min()
+               .map_or(Arrival::Never, Arrival::At)
+}
 
-    let mut possible_end_states : Vec<(String, usize)> = Vec::new();
+/* Solves x = r1 (mod m1), x = r2 (mod m2) for moduli that need not be coprime */
+fn combine_congruences((r1, m1): (u128, u128), (r2, m2): (u128, u128)) -> Option<(u128, u128)> {
+    let (g, p, _q) = extended_gcd(m1 as i128, m2 as i128);
 
-    let mut step: usize = 0;
+    let difference = r2 as i128 - r1 as i128;
+    if difference % g != 0 {
+        return None
+    }
 
-    let mut position = begin_state;
+    let lcm = m1 as i128 / g * m2 as i128;
+    let step = m2 as i128 / g;
+    let k = ((difference / g) % step * (p % step)) % step;
+    let x = (r1 as i128 + m1 as i128 * k).rem_euclid(lcm);
 
-    loop {
-        let instruction_index = step % problem.instructions.len();
+    Some((x as u128, lcm as u128))
+}
 
-        let state = State {
-            position: position.clone(),
-            instruction_index: instruction_index.clone()
-        };
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Instruction {
+    Left,
+    Right,
+}
+
+/* Nodes are interned to ids, names[id] holds the original label and options[id] the (left, right) ids */
+#[derive(Clone, Debug)]
+struct Problem {
+    instructions: Vec<Instruction>,
+    names: Vec<String>,
+    options: Vec<(usize, usize)>
+}
 
-        if seen_states.contains(&state) {
-            break;
+impl Problem {
+    fn next(&self, position: usize, instruction: Instruction) -> usize {
+        let option = self.options[position];
+        match instruction {
+            Instruction::Left => option.0,
+            Instruction::Right => option.1,
         }
+    }
 
-        seen_states.insert(state);
+    fn select(&self, selector: &NodeSelector) -> Vec<usize> {
+        (0..self.names.len()).filter(|id| selector.matches(&self.names[*id])).collect()
+    }
 
-        let instruction =
-            problem.instructions.get(instruction_index).unwrap();
+    /* Nodes reachable from the given ones along any edge, regardless of the instructions */
+    fn reachable_from(&self, starts: &[usize]) -> Vec<bool> {
+        let mut reachable = vec![false; self.names.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
 
-        let option =
-            problem.options.get(&position).unwrap();
+        for start in starts {
+            reachable[*start] = true;
+            queue.push_back(*start);
+        }
 
-        if end_positions.contains(&position) {
-            possible_end_states.push((position, step))
+        while let Some(position) = queue.pop_front() {
+            let (left, right) = self.options[position];
+            for next in [left, right] {
+                if !reachable[next] {
+                    reachable[next] = true;
+                    queue.push_back(next);
+                }
+            }
         }
 
-        position =
-            if instruction == &'L' {
-                option.0.clone()
-            } else {
-                option.1.clone()
-            };
+        reachable
+    }
 
-        step += 1
+    /* Weakly connected components, each as a sorted list of node ids */
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut neighbours: Vec<Vec<usize>> = vec![Vec::new(); self.names.len()];
+        for (id, (left, right)) in self.options.iter().enumerate() {
+            for next in [*left, *right] {
+                neighbours[id].push(next);
+                neighbours[next].push(id);
+            }
+        }
+
+        let mut component_of: Vec<Option<usize>> = vec![None; self.names.len()];
+        let mut components: Vec<Vec<usize>> = Vec::new();
+
+        for root in 0..self.names.len() {
+            if component_of[root].is_some() {
+                continue;
+            }
+
+            let mut component = vec![root];
+            let mut queue: VecDeque<usize> = VecDeque::from([root]);
+            component_of[root] = Some(components.len());
+
+            while let Some(position) = queue.pop_front() {
+                for next in &neighbours[position] {
+                    if component_of[*next].is_none() {
+                        component_of[*next] = Some(components.len());
+                        component.push(*next);
+                        queue.push_back(*next);
+                    }
+                }
+            }
+
+            component.sort();
+            components.push(component);
+        }
+
+        components
     }
+}
 
-    possible_end_states
+/* Which nodes to start or end on, as given on the command line:
+   `AAA` or `AAA,BBB` lists names, `??A` or `*Z` is a glob (`?` is one char, `*` any run),
+   and `starts-with:X` / `ends-with:X` are predicates on the name. */
+#[derive(Clone, Debug)]
+enum NodeSelector {
+    Names(Vec<String>),
+    Glob(String),
+    StartsWith(String),
+    EndsWith(String),
 }
 
+impl NodeSelector {
+    fn parse(selector: &str) -> NodeSelector {
+        if let Some(prefix) = selector.strip_prefix("starts-with:") {
+            NodeSelector::StartsWith(String::from(prefix))
+        } else if let Some(suffix) = selector.strip_prefix("ends-with:") {
+            NodeSelector::EndsWith(String::from(suffix))
+        } else if selector.contains(['*', '?']) {
+            NodeSelector::Glob(String::from(selector))
+        } else {
+            NodeSelector::Names(selector.split(',').map(String::from).collect())
+        }
+    }
 
-#[derive(Clone, Debug)]
-struct Problem {
-    instructions: Vec<char>,
-    options: HashMap<String, (String, String)>
+    fn matches(&self, name: &str) -> bool {
+        match self {
+            NodeSelector::Names(names) => names.iter().any(|x| x == name),
+            NodeSelector::Glob(pattern) => {
+                let pattern: Vec<char> = pattern.chars().collect();
+                let name: Vec<char> = name.chars().collect();
+                glob_matches(&pattern, &name)
+            },
+            NodeSelector::StartsWith(prefix) => name.starts_with(prefix.as_str()),
+            NodeSelector::EndsWith(suffix) => name.ends_with(suffix.as_str()),
+        }
+    }
+}
+
+/* Describes the selected nodes for the answers, e.g. "ZZZ" or "nodes matching ??Z" */
+impl fmt::Display for NodeSelector {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeSelector::Names(names) => write!(f, "{}", names.join(" or ")),
+            NodeSelector::Glob(pattern) => write!(f, "nodes matching {}", pattern),
+            NodeSelector::StartsWith(prefix) => write!(f, "nodes that start with {}", prefix),
+            NodeSelector::EndsWith(suffix) => write!(f, "nodes that end with {}", suffix),
+        }
+    }
+}
+
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    match (pattern.first(), name.first()) {
+        (None, None) => true,
+        (Some('*'), _) => glob_matches(&pattern[1..], name)
+            || (!name.is_empty() && glob_matches(pattern, &name[1..])),
+        (Some('?'), Some(_)) => glob_matches(&pattern[1..], &name[1..]),
+        (Some(p), Some(n)) if p == n => glob_matches(&pattern[1..], &name[1..]),
+        _ => false,
+    }
 }
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
 
     let mut lines = BufReader::new(file_in).lines().map(|x|x.unwrap());
 
-    let instructions: Vec<char> = lines.next().unwrap().chars().collect();
+    let instructions: Vec<Instruction> =
+        lines.next().unwrap().chars().map(|c| match c {
+            'L' => Instruction::Left,
+            'R' => Instruction::Right,
+            _ => panic!("unexpected instruction {}", c),
+        }).collect();
 
     lines.next();
 
+    let mut names: Vec<String> = Vec::new();
+    let mut ids: HashMap<String, usize> = HashMap::new();
+    let mut edges: Vec<(usize, String, String)> = Vec::new();
 
-    let mut options: HashMap<String, (String, String)> = HashMap::new();
-
-    for line in lines {
-        let it = line.replace("=", "")
-                            .replace(",", "")
-                            .replace("(", "")
-                            .replace(")", "");
+    for line in lines.filter(|line| !line.trim().is_empty()) {
+        let it = line.replace(['=', ',', '(', ')'], "");
         let mut it = it.split_whitespace();
-        options.insert(String::from(it.next().unwrap()),
-                     (String::from(it.next().unwrap()),
-                         String::from(it.next().unwrap())));
+        let name = String::from(it.next().unwrap());
+        let id = names.len();
+        ids.insert(name.clone(), id);
+        names.push(name);
+        edges.push((id,
+                    String::from(it.next().unwrap()),
+                    String::from(it.next().unwrap())));
+    }
+
+    let mut options: Vec<(usize, usize)> = vec![(0, 0); names.len()];
+    for (id, left, right) in edges {
+        let lookup = |name: &String| {
+            *ids.get(name).unwrap_or_else(|| panic!("{} has no outgoing edges", name))
+        };
+        options[id] = (lookup(&left), lookup(&right));
     }
 
     Ok(Problem {
         instructions,
+        names,
         options
     })
 }
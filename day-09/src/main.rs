@@ -6,61 +6,353 @@ fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let input = &args[1];
 
+    let policy = match args.iter().position(|arg| arg == "--policy") {
+        Some(i) => Policy::parse(&args[i + 1]),
+        None => Policy::Error,
+    };
+
     let problem = read_input(input)?;
 
-    println!("{:?} is the sum of these extrapolated values",
-             solve1(&problem));
+    if args.iter().any(|arg| arg == "--diagnostics") {
+        for history in &problem.histories {
+            if history.is_empty() {
+                println!("{:?} is empty", history);
+                continue;
+            }
+            let fit = Sequence::new(history).fit();
+            println!("{:?} has degree {}{}, residual {}",
+                     history,
+                     fit.degree,
+                     if fit.vanished { "" } else { " but its differences never vanish" },
+                     fit.residual);
+        }
+    }
+
+    match solve1(&problem, policy) {
+        Ok(sum) => println!("{:?} is the sum of these extrapolated values", sum),
+        Err(error) => println!("Could not extrapolate forwards: {:?}", error),
+    }
 
-    println!("{:?} is the sum of these extrapolated values",
-             solve2(&problem));
+    match solve2(&problem, policy) {
+        Ok(sum) => println!("{:?} is the sum of these extrapolated values", sum),
+        Err(error) => println!("Could not extrapolate backwards: {:?}", error),
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--steps") {
+        let steps = args[i + 1].parse::<i64>().unwrap();
+        for history in &problem.histories {
+            let sequence = Sequence::new(history);
+            println!("{:?} has degree {}, {} steps ahead is {:?} and {} steps back is {:?}",
+                     history,
+                     sequence.degree(),
+                     steps,
+                     sequence.forward(steps, policy),
+                     steps,
+                     sequence.backward(steps, policy));
+        }
+    }
 
     Ok(())
 }
 
-fn solve2(problem: &crate::Problem) -> i64 {
-    fn solve2_h(vec: Vec<i64>) -> i64 {
-        let next_values: Vec<i64> =
-            vec.iter()
-                .enumerate()
-                .skip(1)
-                .map(|(index, value)| value - vec.get(index - 1).unwrap() )
-                .collect();
-        let answer =
-            if next_values.iter().find(|x| x != &&0).is_some() {
-                let p = solve2_h(next_values);
-                *vec.first().unwrap() - p
-            } else {
-                *vec.first().unwrap()
-            };
-        answer
-    }
+fn solve1(problem: &Problem, policy: Policy) -> Result<i128, ExtrapolationError> {
+    problem.histories.iter().map(|x| {
+        Sequence::new(x).forward(1, policy)
+    }).sum()
+}
 
+fn solve2(problem: &Problem, policy: Policy) -> Result<i128, ExtrapolationError> {
     problem.histories.iter().map(|x| {
-        solve2_h(x.clone())
+        Sequence::new(x).backward(1, policy)
     }).sum()
 }
 
-fn solve1(problem: &Problem) -> i64 {
-    fn solve1_h(vec: Vec<i64>) -> i64 {
-        let next_values: Vec<i64> =
-            vec.iter()
-                .enumerate()
-                .skip(1)
-                .map(|(index, value)| value - vec.get(index - 1).unwrap() )
-                .collect();
-        let answer =
-            if next_values.iter().find(|x| x != &&0).is_some() {
-                let p = solve1_h(next_values);
-                *vec.last().unwrap() + p
-            } else {
-                *vec.last().unwrap()
-            };
-        answer
+/* What to do with a history whose differences never vanish before the data runs out */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Policy {
+    /* Refuse to extrapolate */
+    Error,
+    /* Use the polynomial through all values anyway */
+    BestEffort,
+    /* Look for the shortest linear recurrence instead (Berlekamp-Massey) */
+    LinearRecurrence,
+}
+
+impl Policy {
+    fn parse(policy: &str) -> Policy {
+        match policy {
+            "error" => Policy::Error,
+            "best-effort" => Policy::BestEffort,
+            "recurrence" => Policy::LinearRecurrence,
+            _ => panic!("unknown policy {}, expected error, best-effort or recurrence", policy)
+        }
     }
+}
 
-    problem.histories.iter().map(|x| {
-        solve1_h(x.clone())
-    }).sum()
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum ExtrapolationError {
+    Overflow,
+    /* There is nothing to extrapolate from */
+    EmptyHistory,
+    NotPolynomial { degree: usize, residual: i128 },
+    NoRecurrence { length: usize },
+    NotInteger,
+}
+
+/* How well a polynomial describes a history. If the differences vanished, degree is exact and the
+   residual 0. Otherwise degree is that of the polynomial through all values, and the residual the
+   last difference that should have been zero for anything of lower degree, or 0 for an empty
+   history. */
+#[derive(Clone, Debug)]
+struct Fit {
+    degree: usize,
+    vanished: bool,
+    residual: i128,
+}
+
+/* A history described by the leading entries of its difference table, i.e. the coefficients
+   of Newton's forward-difference formula f(x) = sum_k C(x, k) * leading_differences[k]. */
+#[derive(Clone, Debug)]
+struct Sequence {
+    history: Vec<i128>,
+    leading_differences: Vec<i128>,
+    vanished: bool,
+}
+
+impl Sequence {
+    fn new(history: &[i64]) -> Sequence {
+        let history: Vec<i128> = history.iter().map(|x| *x as i128).collect();
+        let mut leading_differences: Vec<i128> = Vec::new();
+        let mut row: Vec<i128> = history.clone();
+
+        while row.iter().any(|x| x != &0) {
+            leading_differences.push(row[0]);
+            row = row.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        }
+
+        Sequence {
+            vanished: !row.is_empty(),
+            history,
+            leading_differences
+        }
+    }
+
+    /* Degree of the polynomial through the history, an all-zero history counts as degree 0 */
+    fn degree(&self) -> usize {
+        self.leading_differences.len().saturating_sub(1)
+    }
+
+    fn fit(&self) -> Fit {
+        Fit {
+            degree: self.degree(),
+            vanished: self.vanished,
+            residual: if self.vanished { 0 } else { self.leading_differences.last().map_or(0, |x| x.abs()) },
+        }
+    }
+
+    /* Value at any integer index, the history itself sits at 0..len */
+    fn value_at(&self, index: i64, policy: Policy) -> Result<i128, ExtrapolationError> {
+        if self.history.is_empty() {
+            return Err(ExtrapolationError::EmptyHistory)
+        }
+
+        if self.vanished {
+            return self.polynomial_at(index).ok_or(ExtrapolationError::Overflow)
+        }
+
+        match policy {
+            Policy::Error => Err(ExtrapolationError::NotPolynomial {
+                degree: self.degree(),
+                residual: self.fit().residual,
+            }),
+            Policy::BestEffort => self.polynomial_at(index).ok_or(ExtrapolationError::Overflow),
+            Policy::LinearRecurrence => LinearRecurrence::find(&self.history)?.value_at(&self.history, index),
+        }
+    }
+
+    fn polynomial_at(&self, index: i64) -> Option<i128> {
+        let x = index as i128;
+        let mut binomial: i128 = 1;
+        let mut value: i128 = 0;
+
+        for (k, difference) in self.leading_differences.iter().enumerate() {
+            if k > 0 {
+                // C(x, k) = C(x, k - 1) * (x - k + 1) / k, and the division is always exact
+                binomial = binomial.checked_mul(x - k as i128 + 1)? / k as i128;
+            }
+            value = value.checked_add(binomial.checked_mul(*difference)?)?;
+        }
+
+        Some(value)
+    }
+
+    fn forward(&self, steps: i64, policy: Policy) -> Result<i128, ExtrapolationError> {
+        self.value_at(self.history.len() as i64 - 1 + steps, policy)
+    }
+
+    fn backward(&self, steps: i64, policy: Policy) -> Result<i128, ExtrapolationError> {
+        self.value_at(-steps, policy)
+    }
+}
+
+/* s[n] = coefficients[0] * s[n - 1] + ... + coefficients[L - 1] * s[n - L] */
+#[derive(Clone, Debug)]
+struct LinearRecurrence {
+    coefficients: Vec<Ratio>,
+}
+
+impl LinearRecurrence {
+    /* Berlekamp-Massey over the rationals. The recurrence is only trusted when the history is
+       at least twice as long as it, otherwise any history would fit. */
+    fn find(history: &[i128]) -> Result<LinearRecurrence, ExtrapolationError> {
+        let sequence: Vec<Ratio> = history.iter().map(|x| Ratio::integer(*x)).collect();
+
+        let (mut connection, length) =
+            berlekamp_massey(&sequence).ok_or(ExtrapolationError::Overflow)?;
+
+        if 2 * length >= history.len() {
+            return Err(ExtrapolationError::NoRecurrence { length })
+        }
+
+        connection.resize(length + 1, Ratio::integer(0));
+        let coefficients: Option<Vec<Ratio>> =
+            connection[1..].iter().map(|c| Ratio::integer(0).sub(*c)).collect();
+
+        Ok(LinearRecurrence {
+            coefficients: coefficients.ok_or(ExtrapolationError::Overflow)?
+        })
+    }
+
+    fn value_at(&self, history: &[i128], index: i64) -> Result<i128, ExtrapolationError> {
+        let overflow = |value: Option<Ratio>| value.ok_or(ExtrapolationError::Overflow);
+        let length = self.coefficients.len();
+        let mut values: Vec<Ratio> = history.iter().map(|x| Ratio::integer(*x)).collect();
+
+        if index < 0 {
+            // Run the recurrence backwards, which needs the oldest term to matter
+            let oldest = *self.coefficients.last().ok_or(ExtrapolationError::NoRecurrence { length })?;
+            if oldest.is_zero() {
+                return Err(ExtrapolationError::NoRecurrence { length })
+            }
+            values.reverse();
+            for _ in 0..index.unsigned_abs() {
+                let n = values.len();
+                let mut value = values[n - length];
+                for i in 1..length {
+                    value = overflow(value.sub(overflow(self.coefficients[i - 1].mul(values[n - length + i]))?))?;
+                }
+                values.push(overflow(value.div(oldest))?);
+            }
+        } else {
+            while (values.len() as i64) <= index {
+                let n = values.len();
+                let mut value = Ratio::integer(0);
+                for i in 1..=length {
+                    value = overflow(value.add(overflow(self.coefficients[i - 1].mul(values[n - i]))?))?;
+                }
+                values.push(value);
+            }
+        }
+
+        let value = if index < 0 { *values.last().unwrap() } else { values[index as usize] };
+        value.to_integer().ok_or(ExtrapolationError::NotInteger)
+    }
+}
+
+/* Connection polynomial C and its length L, such that sum_{i=0..L} C[i] * s[n - i] = 0 */
+fn berlekamp_massey(sequence: &[Ratio]) -> Option<(Vec<Ratio>, usize)> {
+    let mut connection: Vec<Ratio> = vec![Ratio::integer(1)];
+    let mut previous: Vec<Ratio> = vec![Ratio::integer(1)];
+    let mut length: usize = 0;
+    let mut shift: usize = 1;
+    let mut previous_discrepancy = Ratio::integer(1);
+
+    for n in 0..sequence.len() {
+        let mut discrepancy = sequence[n];
+        for i in 1..=length {
+            discrepancy = discrepancy.add(connection[i].mul(sequence[n - i])?)?;
+        }
+
+        if discrepancy.is_zero() {
+            shift += 1;
+            continue;
+        }
+
+        let factor = discrepancy.div(previous_discrepancy)?;
+        let mut next = connection.clone();
+        next.resize(next.len().max(previous.len() + shift), Ratio::integer(0));
+        for (i, coefficient) in previous.iter().enumerate() {
+            next[i + shift] = next[i + shift].sub(factor.mul(*coefficient)?)?;
+        }
+
+        if 2 * length <= n {
+            length = n + 1 - length;
+            previous = connection;
+            previous_discrepancy = discrepancy;
+            shift = 1;
+        } else {
+            shift += 1;
+        }
+        connection = next;
+    }
+
+    Some((connection, length))
+}
+
+/* Exact fraction, always reduced and with a positive denominator. Operations return None on overflow. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Ratio {
+    numerator: i128,
+    denominator: i128,
+}
+
+impl Ratio {
+    fn integer(value: i128) -> Ratio {
+        Ratio { numerator: value, denominator: 1 }
+    }
+
+    fn new(numerator: i128, denominator: i128) -> Option<Ratio> {
+        fn gcd(a: i128, b: i128) -> i128 {
+            if b == 0 { a.abs() } else { gcd(b, a % b) }
+        }
+
+        if denominator == 0 {
+            return None
+        }
+        let divisor = gcd(numerator, denominator) * denominator.signum();
+        Some(Ratio {
+            numerator: numerator / divisor,
+            denominator: denominator / divisor,
+        })
+    }
+
+    fn is_zero(&self) -> bool {
+        self.numerator == 0
+    }
+
+    fn to_integer(self) -> Option<i128> {
+        if self.denominator == 1 { Some(self.numerator) } else { None }
+    }
+
+    fn add(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(self.numerator.checked_mul(other.denominator)?
+                       .checked_add(other.numerator.checked_mul(self.denominator)?)?,
+                   self.denominator.checked_mul(other.denominator)?)
+    }
+
+    fn sub(self, other: Ratio) -> Option<Ratio> {
+        self.add(Ratio { numerator: other.numerator.checked_neg()?, denominator: other.denominator })
+    }
+
+    fn mul(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(self.numerator.checked_mul(other.numerator)?,
+                   self.denominator.checked_mul(other.denominator)?)
+    }
+
+    fn div(self, other: Ratio) -> Option<Ratio> {
+        Ratio::new(self.numerator.checked_mul(other.denominator)?,
+                   self.denominator.checked_mul(other.numerator)?)
+    }
 }
 
 struct Problem {
@@ -68,12 +360,13 @@ struct Problem {
 }
 
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
     let histories: Vec<Vec<i64>> =
         BufReader::new(file_in).lines().map(|x|{
             x.unwrap().split_whitespace().map(|x|{x.parse::<i64>().unwrap()}).collect()
-        }).collect();
+        }).filter(|history: &Vec<i64>| !history.is_empty())
+          .collect();
     Ok(Problem {
         histories
     })
@@ -1,15 +1,27 @@
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::HashSet;
 use std::fs::File;
-use std::io::{self, BufReader, BufRead};
+use std::io::{self, BufReader, BufRead, BufWriter, Write};
 use std::env;
+use std::fmt;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let input = &args[1];
 
+    let method = match args.iter().position(|arg| arg == "--method") {
+        Some(i) => AreaMethod::parse(&args[i + 1]),
+        None => AreaMethod::Scanline,
+    };
+
     let problem = read_input(input)?;
 
-    let solutions  = solve1(&problem);
+    let solutions  = match solve1(&problem, method) {
+        Ok(solutions) => solutions,
+        Err(error) => {
+            println!("The maze is malformed: {}", error);
+            return Ok(())
+        }
+    };
 
     println!("How many steps along the loop does it take to get from the starting position to the point farthest from the starting position? {:?}",
             solutions.0);
@@ -17,96 +29,379 @@ fn main() -> io::Result<()> {
     println!("How many tiles are enclosed by the loop? {:?}",
              solutions.1);
 
+    let maze = PipeMaze::new(&problem).unwrap();
+
+    if args.iter().any(|arg| arg == "--list-enclosed") {
+        for point in maze.enclosed_tiles() {
+            println!("({}, {})", point.x, point.y);
+        }
+    }
+
+    if args.iter().any(|arg| arg == "--render") {
+        print!("{}", maze.render_ansi());
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--render-ppm") {
+        let mut out = BufWriter::new(File::create(&args[i + 1])?);
+        maze.write_ppm(&mut out)?;
+    }
+
     Ok(())
 }
 
-fn solve1(problem: &Problem) -> (usize, usize) {
+fn solve1(problem: &Problem, method: AreaMethod) -> Result<(usize, usize), MazeError> {
 
-    let mut visited_positions: HashMap<Point, usize> = HashMap::new();
+    let maze = PipeMaze::new(problem)?;
 
-    let mut deque: VecDeque<Point> = VecDeque::new();
+    Ok((maze.main_loop.len() / 2, maze.enclosed_count(method)))
+}
 
-    let mut dxdy :  HashMap<Point, String> = HashMap::new();
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum AreaMethod {
+    /* Parity of the loop crossings along every row */
+    Scanline,
+    /* Shoelace area of the loop polygon, turned into a tile count by Pick's theorem */
+    Pick,
+}
 
-    dxdy.insert(Point { x:0, y:1 }, String::from("|LJ")); // North
-    dxdy.insert(Point { x:0, y:-1 }, String::from("|7F")); // South
-    dxdy.insert(Point { x: 1, y: 0 }, String::from("-J7")); // East
-    dxdy.insert(Point { x: -1, y: 0 }, String::from("-LF")); // West
+impl AreaMethod {
+    fn parse(method: &str) -> AreaMethod {
+        match method {
+            "scanline" => AreaMethod::Scanline,
+            "pick" => AreaMethod::Pick,
+            _ => panic!("unknown method {}, expected scanline or pick", method)
+        }
+    }
+}
 
-    let y =
-        problem.maze.iter()
-                    .enumerate()
-                    .find(|(_y, line)|
-                              {
-                                  line.iter().enumerate().find(|(_x, c)| { c == &&'S' }).is_some()
-                              }).map(|(y, _line)| y).unwrap();
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum Direction {
+    North,
+    East,
+    South,
+    West,
+}
 
-    let x = problem.maze.iter().nth(y).map(|line| {
-        let x = line.iter().enumerate().find(|(_x, c)| { c == &&'S' }).map(|(x,_c)| x);
-        x.unwrap()
-    }).unwrap();
+impl Direction {
+    fn dxdy(&self) -> Point {
+        match self {
+            Direction::North => Point { x: 0, y: -1 },
+            Direction::East => Point { x: 1, y: 0 },
+            Direction::South => Point { x: 0, y: 1 },
+            Direction::West => Point { x: -1, y: 0 },
+        }
+    }
 
-    let start = Point {
-        x: x as i64,
-        y: y as i64,
-    };
+    fn opposite(&self) -> Direction {
+        match self {
+            Direction::North => Direction::South,
+            Direction::East => Direction::West,
+            Direction::South => Direction::North,
+            Direction::West => Direction::East,
+        }
+    }
+}
+
+const DIRECTIONS: [Direction; 4] = [Direction::North, Direction::East, Direction::South, Direction::West];
+
+/* The directions a pipe tile opens to, empty for ground and anything unknown */
+fn connections(tile: char) -> Vec<Direction> {
+    match tile {
+        '|' => vec![Direction::North, Direction::South],
+        '-' => vec![Direction::East, Direction::West],
+        'L' => vec![Direction::North, Direction::East],
+        'J' => vec![Direction::North, Direction::West],
+        '7' => vec![Direction::South, Direction::West],
+        'F' => vec![Direction::East, Direction::South],
+        _ => vec![],
+    }
+}
+
+fn tile_connecting(directions: &[Direction]) -> char {
+    "|-LJ7F".chars()
+            .find(|tile| {
+                let tile_connections = connections(*tile);
+                directions.iter().all(|direction| tile_connections.contains(direction))
+            })
+            .unwrap()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum MazeError {
+    NoStart,
+    MultipleStarts(usize),
+    /* S needs exactly two neighbours that connect back to it */
+    StartConnections(usize),
+    /* The pipe at this point doesn't connect back to the tile we came from */
+    DeadEnd(Point),
+}
+
+impl fmt::Display for MazeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MazeError::NoStart => write!(f, "there is no starting position S"),
+            MazeError::MultipleStarts(amount) =>
+                write!(f, "there are {} starting positions, expected exactly one", amount),
+            MazeError::StartConnections(amount) =>
+                write!(f, "S connects to {} pipes, expected exactly two", amount),
+            MazeError::DeadEnd(point) =>
+                write!(f, "the loop dead-ends at ({}, {})", point.x, point.y),
+        }
+    }
+}
+
+/* The maze with S replaced by the pipe it must be, plus the main loop in walking order,
+   starting at S */
+struct PipeMaze {
+    tiles: Vec<Vec<char>>,
+    main_loop: Vec<Point>,
+}
+
+impl PipeMaze {
+    fn new(problem: &Problem) -> Result<PipeMaze, MazeError> {
+        let starts: Vec<Point> =
+            problem.points().filter(|point| problem.char_at(point) == 'S').collect();
+
+        let start = match starts.len() {
+            0 => return Err(MazeError::NoStart),
+            1 => starts[0].clone(),
+            amount => return Err(MazeError::MultipleStarts(amount)),
+        };
+
+        let start_directions: Vec<Direction> =
+            DIRECTIONS.iter()
+                      .filter(|direction| {
+                          let next_point = start.add(&direction.dxdy());
+                          problem.contains(&next_point)
+                              && connections(problem.char_at(&next_point)).contains(&direction.opposite())
+                      })
+                      .copied()
+                      .collect();
+
+        if start_directions.len() != 2 {
+            return Err(MazeError::StartConnections(start_directions.len()))
+        }
+
+        let mut tiles = problem.maze.clone();
+        tiles[start.y as usize][start.x as usize] = tile_connecting(&start_directions);
+
+        let mut maze = PipeMaze {
+            tiles,
+            main_loop: vec![start.clone()],
+        };
+
+        let mut point = start.clone();
+        let mut direction = start_directions[0];
+
+        loop {
+            let next_point = point.add(&direction.dxdy());
 
-    visited_positions.insert(start.clone(), 0);
+            if !maze.contains(&next_point)
+                || !connections(maze.char_at(&next_point)).contains(&direction.opposite()) {
+                return Err(MazeError::DeadEnd(point))
+            }
+
+            if next_point == start {
+                break;
+            }
 
-    deque.push_back(start.clone());
+            direction = connections(maze.char_at(&next_point))
+                .into_iter()
+                .find(|next_direction| next_direction != &direction.opposite())
+                .unwrap();
 
-    while !deque.is_empty() {
+            maze.main_loop.push(next_point.clone());
+            point = next_point;
+        }
 
-        let point = deque.pop_front().unwrap();
+        Ok(maze)
+    }
 
-        dxdy.iter()
-            .for_each(|(dxdy, allowed_chars)| {
+    /* The tiles enclosed by the main loop, found row by row in reading order */
+    fn enclosed_tiles(&self) -> Vec<Point> {
+        let on_loop: HashSet<Point> = self.main_loop.iter().cloned().collect();
 
-                let next_point = point.add(dxdy);
+        let mut inside_positions: Vec<Point> = Vec::new();
 
-                if next_point.y >= 0 && next_point.y < problem.height()
-                    && next_point.x >= 0 && next_point.x < problem.width()
-                    && allowed_chars.contains(problem.char_at(&next_point))
-                    && (!visited_positions.contains_key(&next_point)
-                    || visited_positions.get(&point).unwrap()
-                    < visited_positions.get(&next_point).unwrap()) {
+        for y in 0..self.height() {
+            let mut inside = false;
 
-                    visited_positions.insert(next_point.clone(),
-                                             visited_positions.get(&point).unwrap() + 1);
-                    deque.push_back(next_point);
+            for x in 0..self.width() {
+                let point = Point {
+                    x,y
+                };
+
+                // Count the loop tiles that connect north, a ray through the top half of each row
+                // then crosses the loop exactly once for each of them.
+                if on_loop.contains(&point) {
+                    if connections(self.char_at(&point)).contains(&Direction::North) {
+                        inside = !inside;
+                    }
+                }
+                else if inside {
+                    inside_positions.push(point);
                 }
-            });
+            }
+        }
+
+        inside_positions
     }
 
-    //problem.debug(&visited_positions);
-    let sol1 = *visited_positions.iter().map(|(_k,v)| v).max().unwrap();
+    fn enclosed_count(&self, method: AreaMethod) -> usize {
+        let scanline = || self.enclosed_tiles().len();
 
-    let mut inside_positions: HashSet<Point> = HashSet::new();
+        // The loop runs through tile centres, so its area A = i + b/2 - 1 by Pick's theorem,
+        // with b the number of loop tiles and i the enclosed tiles we are after.
+        let pick = || {
+            let twice_area: i64 =
+                self.main_loop.iter()
+                              .zip(self.main_loop.iter().cycle().skip(1))
+                              .map(|(p, q)| p.x * q.y - q.x * p.y)
+                              .sum();
+            (twice_area.unsigned_abs() as usize + 2 - self.main_loop.len()) / 2
+        };
 
+        let count = match method {
+            AreaMethod::Scanline => scanline(),
+            AreaMethod::Pick => pick(),
+        };
 
-    for y in 0..problem.height() {
-        let mut inside = false;
+        debug_assert_eq!(scanline(), pick(), "scanline and Pick's theorem disagree on the enclosed area");
 
-        for x in 0..problem.width() {
-            let crossing_nodes: Vec<char> = vec!['|', 'F', '7', 'F', 'J'];
-            let point = Point {
-                x,y
-            };
+        count
+    }
+
+    /* Every tile of the maze classified for rendering, row by row */
+    fn classify(&self) -> Vec<Vec<TileKind>> {
+        let on_loop: HashSet<Point> = self.main_loop.iter().cloned().collect();
+        let inside: HashSet<Point> = self.enclosed_tiles().into_iter().collect();
+
+        (0..self.height()).map(|y| {
+            (0..self.width()).map(|x| {
+                let point = Point { x, y };
+                if on_loop.contains(&point) {
+                    TileKind::Loop
+                } else if inside.contains(&point) {
+                    TileKind::Inside
+                } else {
+                    TileKind::Outside
+                }
+            }).collect()
+        }).collect()
+    }
 
-            if crossing_nodes.contains(&problem.char_at(&point)) && visited_positions.contains_key(&point)  {
-                inside = !inside;
+    /* The maze in box-drawing characters: the main loop in bold, enclosed tiles on a green
+       and outside tiles on a blue background. */
+    fn render_ansi(&self) -> String {
+        const RESET: &str = "\x1b[0m";
+
+        let mut rendered = String::new();
+
+        for (y, row) in self.classify().iter().enumerate() {
+            for (x, kind) in row.iter().enumerate() {
+                let tile = self.tiles[y][x];
+                let (style, glyph) = match kind {
+                    TileKind::Loop => ("\x1b[1;93m", heavy_glyph(tile)),
+                    TileKind::Inside => ("\x1b[42;30m", light_glyph(tile)),
+                    TileKind::Outside => ("\x1b[44;37m", light_glyph(tile)),
+                };
+                rendered.push_str(style);
+                rendered.push(glyph);
+                rendered.push_str(RESET);
             }
-            else if inside && !visited_positions.contains_key(&point) {
-                inside_positions.insert(point);
+            rendered.push('\n');
+        }
+
+        rendered
+    }
+
+    /* Binary PPM with every tile drawn as 3x3 pixels, the pipes of the main loop in white */
+    fn write_ppm(&self, out: &mut impl Write) -> io::Result<()> {
+        const SCALE: usize = 3;
+        const LOOP: [u8; 3] = [255, 255, 255];
+        const INSIDE: [u8; 3] = [40, 160, 60];
+        const OUTSIDE: [u8; 3] = [30, 40, 110];
+
+        let classified = self.classify();
+        let width = self.width() as usize;
+
+        writeln!(out, "P6\n{} {}\n255", width * SCALE, classified.len() * SCALE)?;
+
+        for (y, row) in classified.iter().enumerate() {
+            for dy in 0..SCALE {
+                for (x, kind) in row.iter().enumerate() {
+                    for dx in 0..SCALE {
+                        let background = match kind {
+                            TileKind::Inside => INSIDE,
+                            _ => OUTSIDE,
+                        };
+
+                        // The centre pixel and the pixels towards each connection make up the pipe
+                        let pipe = kind == &TileKind::Loop && match (dx, dy) {
+                            (1, 1) => true,
+                            (1, 0) => connections(self.tiles[y][x]).contains(&Direction::North),
+                            (2, 1) => connections(self.tiles[y][x]).contains(&Direction::East),
+                            (1, 2) => connections(self.tiles[y][x]).contains(&Direction::South),
+                            (0, 1) => connections(self.tiles[y][x]).contains(&Direction::West),
+                            _ => false,
+                        };
+
+                        out.write_all(if pipe { &LOOP } else { &background })?;
+                    }
+                }
             }
         }
+
+        out.flush()
     }
 
-    problem.debug(&inside_positions);
+    fn height(&self) -> i64 {
+        self.tiles.len() as i64
+    }
+
+    fn width(&self) -> i64 {
+        self.tiles.first().map_or(0, |row| row.len()) as i64
+    }
+
+    fn contains(&self, point: &Point) -> bool {
+        point.y >= 0 && point.y < self.height() && point.x >= 0 && point.x < self.width()
+    }
 
-    (sol1, inside_positions.len())
+    fn char_at(&self, point: &Point) -> char {
+        self.tiles[point.y as usize][point.x as usize]
+    }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum TileKind {
+    Loop,
+    Inside,
+    Outside,
+}
+
+fn light_glyph(tile: char) -> char {
+    match tile {
+        '|' => '│',
+        '-' => '─',
+        'L' => '└',
+        'J' => '┘',
+        '7' => '┐',
+        'F' => '┌',
+        _ => ' ',
+    }
+}
+
+fn heavy_glyph(tile: char) -> char {
+    match tile {
+        '|' => '┃',
+        '-' => '━',
+        'L' => '┗',
+        'J' => '┛',
+        '7' => '┓',
+        'F' => '┏',
+        _ => ' ',
+    }
+}
 
 struct Problem {
     maze: Vec<Vec<char>>
@@ -117,26 +412,19 @@ impl Problem {
         self.maze.len() as i64
     }
 
-    fn width(&self) -> i64 {
-        self.maze.get(0).unwrap().len() as i64
+    fn contains(&self, point: &Point) -> bool {
+        point.y >= 0 && point.y < self.height()
+            && point.x >= 0 && point.x < self.maze[point.y as usize].len() as i64
     }
 
     fn char_at(&self, point: &Point) -> char {
         *self.maze.get(point.y as usize).unwrap().get(point.x as usize).unwrap()
     }
 
-    fn debug(&self, inside_positions: &HashSet<Point>) {
-        for y in 0..self.height() {
-            let mut  row = self.maze.get(y as usize).unwrap().clone();
-            for x in 0..self.width() {
-                let point = Point {x, y };
-                if inside_positions.contains(&point) {
-                    row[x as usize] = 'I'
-                }
-            }
-            let s: String =  row.iter().collect();
-            println!("{:?}", s);
-        }
+    fn points(&self) -> impl Iterator<Item = Point> + '_ {
+        self.maze.iter().enumerate().flat_map(|(y, row)| {
+            (0..row.len()).map(move |x| Point { x: x as i64, y: y as i64 })
+        })
     }
 }
 
@@ -156,7 +444,7 @@ impl Point {
 }
 
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
     let maze: Vec<Vec<char>> = BufReader::new(file_in).lines().map(|x|x.unwrap().chars().collect()).collect();
     Ok(Problem{
@@ -1,11 +1,6 @@
-extern crate core;
-
-use std::cmp::{max, min};
-use std::collections::HashSet;
 use std::fs::File;
-use std::io::{self, BufReader, BufRead};
+use std::io::{self, BufReader, BufRead, BufWriter, Write};
 use std::env;
-use itertools::Itertools;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -13,96 +8,227 @@ fn main() -> io::Result<()> {
 
     let problem = read_input(input)?;
 
+    let flag = |flag: &str| args.iter()
+                                .position(|arg| arg == flag)
+                                .map(|i| args[i + 1].parse::<i64>().unwrap());
+    let growth = |factor: i64| {
+        if args.iter().any(|arg| arg == "--proportional") {
+            Growth::Proportional(factor)
+        } else {
+            Growth::Uniform(factor)
+        }
+    };
+    let puzzle = |factor: i64| ExpansionModel {
+        rows: Growth::Uniform(factor),
+        columns: Growth::Uniform(factor),
+        threshold: 1,
+    };
+
     println!("What is the sum of these lengths? {:?}",
-             problem.solve1());
+             problem.sum_of_distances(&puzzle(2)));
 
     println!("What is the sum of these lengths? {:?}",
-             problem.solve2());
+             problem.sum_of_distances(&puzzle(1000000)));
+
+    // The model the flags describe, the part 1 expansion unless told otherwise
+    let factor = flag("--factor").unwrap_or(2);
+    let model = ExpansionModel {
+        rows: growth(flag("--row-factor").unwrap_or(factor)),
+        columns: growth(flag("--column-factor").unwrap_or(factor)),
+        threshold: flag("--threshold").unwrap_or(1) as usize,
+    };
+
+    let customised = ["--factor", "--row-factor", "--column-factor", "--proportional", "--threshold"]
+        .iter()
+        .any(|flag| args.iter().any(|arg| arg == flag));
+    if customised {
+        println!("With {:?} the sum of these lengths is {}", model, problem.sum_of_distances(&model));
+    }
+
+    let galaxy = |i: usize| args[i].parse::<usize>().unwrap();
+
+    if let Some(i) = args.iter().position(|arg| arg == "--pair") {
+        println!("Galaxy {} and {} are {} apart",
+                 galaxy(i + 1), galaxy(i + 2), problem.distance(galaxy(i + 1), galaxy(i + 2), &model));
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--nearest") {
+        match problem.nearest(galaxy(i + 1), &model) {
+            Some((other, distance)) =>
+                println!("Galaxy {} is nearest to galaxy {}, {} away", galaxy(i + 1), other, distance),
+            None => println!("Galaxy {} is all alone", galaxy(i + 1)),
+        }
+    }
+
+    if args.iter().any(|arg| arg == "--farthest") {
+        if let Some((l, r, distance)) = problem.farthest_pair(&model) {
+            println!("Galaxy {} and {} are farthest apart, {} away", l, r, distance);
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--export") {
+        let mut out = BufWriter::new(File::create(&args[i + 1])?);
+        for (galaxy, point) in problem.expanded(&model).iter().enumerate() {
+            writeln!(out, "{},{},{}", galaxy + 1, point.x, point.y)?;
+        }
+    }
 
     Ok(())
 }
 
-struct Problem {
-    nodes: HashSet<Point>,
-    empty_rows: HashSet<usize>,
-    empty_columns: HashSet<usize>,
+/* How much a single expanding line grows, given its index in the original image */
+#[derive(Clone, Copy, Debug)]
+enum Growth {
+    /* Every expanding line becomes factor lines wide */
+    Uniform(i64),
+    /* An expanding line becomes 1 + factor * index lines wide, so growth increases with the
+       distance from the origin */
+    Proportional(i64),
 }
 
-impl Problem {
+impl Growth {
+    fn width(&self, index: i64) -> i64 {
+        match self {
+            Growth::Uniform(factor) => *factor,
+            Growth::Proportional(factor) => 1 + factor * index,
+        }
+    }
+}
 
-    fn solve1(&self) -> i64 {
+/* Rows and columns expand independently, and a line expands when it holds fewer than
+   threshold galaxies (1 being the puzzle's "empty" rule). */
+#[derive(Clone, Copy, Debug)]
+struct ExpansionModel {
+    rows: Growth,
+    columns: Growth,
+    threshold: usize,
+}
 
-        self.nodes.iter()
-                  .tuple_combinations()
-                  .map(|(l,r)|  self.distance(l.clone(), r.clone(), 2))
-                  .sum()
-    }
+/* Expanding lines along one axis, with the extra width they add up to (and excluding) each one */
+struct AxisExpansion {
+    lines: Vec<i64>,
+    extra_before: Vec<i64>,
+}
 
-    fn solve2(&self) -> i64 {
+impl AxisExpansion {
+    fn new(galaxy_counts: &[usize], growth: Growth, threshold: usize) -> AxisExpansion {
+        let lines: Vec<i64> =
+            (0..galaxy_counts.len()).filter(|line| galaxy_counts[*line] < threshold)
+                                    .map(|line| line as i64)
+                                    .collect();
 
-        self.nodes.iter()
-            .tuple_combinations()
-            .map(|(l,r)|  self.distance(l.clone(), r.clone(), 1000000))
-            .sum()
+        let mut extra_before = vec![0];
+        for line in &lines {
+            extra_before.push(extra_before.last().unwrap() + growth.width(*line) - 1);
+        }
+
+        AxisExpansion {
+            lines,
+            extra_before,
+        }
     }
 
-    fn distance(&self, start: Point, end: Point, space: i64) -> i64 {
-        
-        let minx = min(start.x, end.x);
-        let maxx = max(start.x, end.x);
+    fn expand(&self, coordinate: i64) -> i64 {
+        coordinate + self.extra_before[self.lines.partition_point(|line| line < &coordinate)]
+    }
+}
+
+/* Galaxies are numbered from 1 in reading order, like in the puzzle. Rows and columns keep how
+   many galaxies they hold, the expansion model decides what that means for their width. */
+struct Problem {
+    nodes: Vec<Point>,
+    row_counts: Vec<usize>,
+    column_counts: Vec<usize>,
+}
 
-        let miny = min(start.y, end.y);
-        let maxy = max(start.y, end.y);
+impl Problem {
 
+    /* Sum of the Manhattan distances between all pairs. Per axis, the i-th smallest of n
+       coordinates is subtracted by the i before it and added for the n - i - 1 after it. */
+    fn sum_of_distances(&self, model: &ExpansionModel) -> i64 {
+        fn sum_of_differences(mut values: Vec<i64>) -> i64 {
+            values.sort_unstable();
+            let mut prefix_sum = 0;
+            values.iter().enumerate().map(|(i, value)| {
+                let sum = value * i as i64 - prefix_sum;
+                prefix_sum += value;
+                sum
+            }).sum()
+        }
 
-        let extra_y: i64 = self.empty_rows.iter().map(|y| *y as i64).map(|y| if y >= miny && y <= maxy { space - 1 } else { 0 }).sum();
-        let extra_x: i64 = self.empty_columns.iter().map(|x| *x as i64).map(|x| if x >= minx && x <= maxx { space - 1 } else { 0 }).sum();
+        let expanded = self.expanded(model);
 
-        (maxx - minx + extra_x) +  (maxy - miny + extra_y)
+        sum_of_differences(expanded.iter().map(|point| point.x).collect())
+            + sum_of_differences(expanded.iter().map(|point| point.y).collect())
     }
 
-    fn new(map: Vec<Vec<char>>) -> Self {
+    fn expanded(&self, model: &ExpansionModel) -> Vec<Point> {
+        let columns = AxisExpansion::new(&self.column_counts, model.columns, model.threshold);
+        let rows = AxisExpansion::new(&self.row_counts, model.rows, model.threshold);
 
-        let mut nodes: HashSet<Point> = HashSet::new();
+        self.nodes.iter().map(|point| Point {
+            x: columns.expand(point.x),
+            y: rows.expand(point.y),
+        }).collect()
+    }
 
-        let empty_rows: HashSet<usize> = HashSet::from_iter(
-            map.iter().enumerate()
-                          .filter(|(_index, row)| {
-                              row.iter().find(|c| c == &&'#' ).is_none()
-                          })
-                         .map(|(index, _row)| index)
-        );
+    fn distance(&self, start: usize, end: usize, model: &ExpansionModel) -> i64 {
+        let expanded = self.expanded(model);
+        expanded[start - 1].manhattan(&expanded[end - 1])
+    }
+
+    fn nearest(&self, galaxy: usize, model: &ExpansionModel) -> Option<(usize, i64)> {
+        let expanded = self.expanded(model);
+        let point = &expanded[galaxy - 1];
+
+        expanded.iter()
+                .enumerate()
+                .filter(|(i, _other)| i + 1 != galaxy)
+                .map(|(i, other)| (i + 1, point.manhattan(other)))
+                .min_by_key(|(_i, distance)| *distance)
+    }
 
-        let empty_columns : HashSet<usize> =
-            HashSet::from_iter(
-                (0..(map.get(0).unwrap().len())).filter(|x| {
-                    (0..(map.len())).find(|y|
-                        map.get(*y).unwrap().get(*x).unwrap() == &'#'
-                    ).is_none()
-                })
-            );
+    /* The Manhattan distance is max(|du|, |dv|) with u = x + y and v = x - y, so the farthest
+       pair is made of the extremes along u or along v. */
+    fn farthest_pair(&self, model: &ExpansionModel) -> Option<(usize, usize, i64)> {
+        let expanded = self.expanded(model);
 
-        for y in 0..map.len() {
+        let extremes = |key: &dyn Fn(&Point) -> i64| {
+            let (low, _) = expanded.iter().enumerate().min_by_key(|(_i, point)| key(point))?;
+            let (high, _) = expanded.iter().enumerate().max_by_key(|(_i, point)| key(point))?;
+            Some((low.min(high) + 1, low.max(high) + 1, expanded[low].manhattan(&expanded[high])))
+        };
 
-            let row = map.get(y).unwrap();
+        let along_u = extremes(&|point: &Point| point.x + point.y)?;
+        let along_v = extremes(&|point: &Point| point.x - point.y)?;
 
-            for x in 0..row.len() {
+        Some(if along_u.2 >= along_v.2 { along_u } else { along_v })
+    }
 
-                let point =  Point {
-                    x:x as i64,
-                    y:y as i64
-                };
+    fn new(map: Vec<Vec<char>>) -> Self {
 
-                if row.get(x).unwrap() == &'#' {
-                    nodes.insert(point.clone());
+        let mut nodes: Vec<Point> = Vec::new();
+
+        let mut row_counts: Vec<usize> = vec![0; map.len()];
+        let mut column_counts: Vec<usize> = vec![0; map.first().map_or(0, |row| row.len())];
+
+        for (y, row) in map.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if c == &'#' {
+                    row_counts[y] += 1;
+                    column_counts[x] += 1;
+                    nodes.push(Point {
+                        x: x as i64,
+                        y: y as i64
+                    });
                 }
             }
         }
 
         Problem {
             nodes,
-            empty_rows,
-            empty_columns,
+            row_counts,
+            column_counts,
         }
     }
 }
@@ -113,11 +239,18 @@ struct Point {
     y: i64,
 }
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+impl Point {
+    fn manhattan(&self, other: &Point) -> i64 {
+        (self.x - other.x).abs() + (self.y - other.y).abs()
+    }
+}
+
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
     let map: Vec<Vec<char>> =
         BufReader::new(file_in).lines()
                                      .map(|line|{line.unwrap().chars().collect()})
+                                     .filter(|row: &Vec<char>| !row.is_empty())
                                      .collect();
 
 
@@ -1,3 +1,5 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
 use std::env;
@@ -12,17 +14,115 @@ fn main() -> io::Result<()> {
 
     println!("What is the new sum of possible arrangement counts? {:?}", solve2(&problem));
 
+    if let Some(i) = args.iter().position(|arg| arg == "--unfold") {
+        let copies = args[i + 1].parse::<usize>().unwrap();
+        let separator = args.iter()
+                            .position(|arg| arg == "--separator")
+                            .map_or('?', |i| args[i + 1].chars().next().unwrap());
+        let modulus = match args.iter().position(|arg| arg == "--modulo") {
+            Some(i) => match args[i + 1].parse::<u64>() {
+                Ok(modulus) if modulus >= 2 => Some(modulus),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                                               format!("--modulo needs a whole number of at least 2, not {}", args[i + 1]))),
+            },
+            None => None,
+        };
+
+        let sum = match modulus {
+            Some(modulus) => solve_unfolded(&problem, copies, separator, &Modular::new(modulus))
+                .map(|sum| format!("{} (mod {})", sum, modulus)),
+            None => solve_unfolded(&problem, copies, separator, &Exact)
+                .map(|sum| sum.to_string()),
+        };
+
+        match sum {
+            Ok(sum) => println!("Unfolded {} times with '{}' the sum of counts is {}", copies, separator, sum),
+            Err(error) => println!("Could not unfold {} times: {}", copies, error),
+        }
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--inspect") {
+        let configuration = &problem.configurations[args[i + 1].parse::<usize>().unwrap() - 1];
+        let flag = |flag: &str, default: usize| args.iter()
+                                                    .position(|arg| arg == flag)
+                                                    .map_or(default, |i| args[i + 1].parse::<usize>().unwrap());
+        inspect(configuration, flag("--limit", 10), flag("--seed", 2023) as u64);
+    }
+
     Ok(())
 }
 
-fn solve1(problem: &Problem) -> usize {
+fn inspect(configuration: &Configuration, limit: usize, seed: u64) {
+    let record: String = configuration.chars.iter().collect();
+    println!("{} {:?} has {} arrangements",
+             record, configuration.groups, configuration.possible_configurations());
+
+    if let Some(reason) = configuration.explain() {
+        match reason {
+            Unsatisfiable::GroupDoesNotFit { group, size } =>
+                println!("\tgroup {} of size {} can't be placed after the groups before it", group, size),
+            Unsatisfiable::LeftoverDamage =>
+                println!("\tevery group fits, but damaged springs are left over"),
+        }
+        return
+    }
+
+    for arrangement in configuration.arrangements().take(limit) {
+        println!("\t{}", arrangement);
+    }
+
+    let mut rng = Rng::new(seed);
+    println!("\ta random arrangement (seed {}): {}", seed, configuration.sample(&mut rng).unwrap());
+
+    for (index, c) in configuration.forced_cells() {
+        println!("\tposition {} is always '{}'", index, c);
+    }
+}
+
+fn solve1(problem: &Problem) -> u128 {
     problem.configurations.iter()
-                          .map(|x| { x.possible_configurations() } ).sum()
+                          .map(|x| {
+                              let count = x.possible_configurations();
+                              debug_assert_eq!(count, x.possible_configurations_brute_force() as u128);
+                              count
+                          }).sum()
 }
 
-fn solve2(problem: &Problem) -> usize {
+fn solve2(problem: &Problem) -> u128 {
     problem.configurations.iter()
-        .map(|x| { x.possible_fixed_configurations() } ).sum()
+        .map(|x| {
+            let count = x.possible_fixed_configurations(5, '?');
+            debug_assert_eq!(Ok(BigUint::from_u128(count)), x.unfolded_count(5, '?', &Exact));
+            count
+        } ).sum()
+}
+
+/* Sum of the unfolded counts, modulo the given number or exact. Exact counts of records whose
+   groups drift between copies are found block by block, so before counting anything this checks
+   that the whole input can be done in reasonable time. */
+fn solve_unfolded<R: Ring>(problem: &Problem,
+                           copies: usize,
+                           separator: char,
+                           ring: &R) -> Result<R::Value, UnfoldError> {
+    const MAX_BLOCK_WORK: usize = 300_000_000;
+
+    let weights: Vec<usize> =
+        problem.configurations.iter()
+                              .map(|x| x.unfolding(separator).map_or(0, |unfolding| unfolding.block_weight()))
+                              .collect();
+    let weight: usize = weights.iter().sum();
+
+    if ring.modulus().is_none() && copies.saturating_mul(copies).saturating_mul(weight) > MAX_BLOCK_WORK {
+        return Err(UnfoldError::UnboundedDrift {
+            records: (1..=weights.len()).filter(|record| weights[record - 1] > 0).collect(),
+            copies,
+            max_copies: (MAX_BLOCK_WORK / weight).isqrt(),
+        })
+    }
+
+    problem.configurations.iter().try_fold(ring.zero(), |sum, x| {
+        Ok(ring.add(&sum, &x.unfolded_count(copies, separator, ring)?))
+    })
 }
 
 struct Problem {
@@ -35,48 +135,163 @@ struct Configuration {
 }
 
 impl Configuration {
-    fn possible_configurations(&self) -> usize {
-        let x = Configuration::possible_configurations_h(self.chars.clone(),
-                                                                     &self.groups);
-        // println!("x: {:?}", x);
-        x
+    fn possible_configurations(&self) -> u128 {
+        ArrangementCounter::new(&self.chars, &self.groups).count()
     }
 
-    fn possible_fixed_configurations(&self) -> usize {
+    /* Counts the arrangements of the record unfolded into copies copies joined by separator,
+       by building the whole unfolded record. See unfolded_count for large numbers of copies. */
+    fn possible_fixed_configurations(&self, copies: usize, separator: char) -> u128 {
         let mut chars: Vec<char> = Vec::new();
         let mut groups: Vec<usize> =  Vec::new();
 
-        chars.extend(self.chars.iter());
-        groups.extend(self.groups.iter());
-
-        (0..4).for_each(|_|{
-            chars.push('?');
+        (0..copies).for_each(|copy|{
+            if copy > 0 {
+                chars.push(separator);
+            }
             chars.extend(self.chars.iter());
             groups.extend(self.groups.iter());
         });
 
-        let s : String = chars.iter().collect();
-        // println!("chars: {:?}, groups: {:?}", s, groups);
+        ArrangementCounter::new(&chars, &groups).count()
+    }
+
+    /* Same count as possible_fixed_configurations, but without building the unfolded record.
+
+       A state is (offset, run): offset is how many groups ahead (or behind) of copies * |groups|
+       we are, run the length of the group we are in. The effect of one more "separator + record"
+       block on these states doesn't depend on which copy we are at, so when only finitely many
+       states can be reached it is a transfer matrix, raised to the power copies - 1 by repeated
+       squaring. Some records let the offset drift without bound though (think "???" with groups
+       1, where a copy can hold 0, 1 or 2 of them), and their counts follow no fixed-size
+       recurrence at all. Exact counts of those are found block by block, in time quadratic in
+       copies, which solve_unfolded checks stays affordable. Modulo a prime, their counts follow
+       a recurrence with polynomial coefficients instead, guessed from the first few hundred
+       counts and run up to copies in linear time. */
+    fn unfolded_count<R: Ring>(&self, copies: usize, separator: char, ring: &R) -> Result<R::Value, UnfoldError> {
+        if copies == 0 {
+            return Ok(ring.lift(1))
+        }
+
+        match self.unfolding(separator) {
+            Some(unfolding) => unfolding.count(copies, ring),
+            None => {
+                let damaged = self.chars.contains(&'#') || (copies > 1 && separator == '#');
+                Ok(ring.lift(!damaged as u128))
+            }
+        }
+    }
+
+    /* The blocks the record unfolds into, None when it has no groups */
+    fn unfolding(&self, separator: char) -> Option<Unfolding> {
+        const MAX_STATES: usize = 256;
+
+        if self.groups.is_empty() {
+            return None
+        }
+
+        let period = self.groups.len() as i64;
+        let longest = *self.groups.iter().max().unwrap();
+        let last = *self.groups.last().unwrap();
+        let phase_of = |offset: i64, run: usize| (offset.rem_euclid(period), run);
+
+        let mut block = vec![separator];
+        block.extend(self.chars.iter());
+
+        // Where the first copy can leave us, as offsets relative to one period of groups
+        let start: Vec<((i64, usize), u128)> =
+            advance_groups(&self.chars, &self.groups, HashMap::from([((0, 0), 1)]))
+                .into_iter()
+                .map(|((done, run), count)| ((done - period, run), count))
+                .collect();
+
+        // One block moves phase (offset mod |groups|, run) to another phase, shifting the offset
+        let phases: Vec<(i64, usize)> =
+            (0..period).flat_map(|phase| (0..=longest).map(move |run| (phase, run))).collect();
+
+        let mut transitions: Transitions = HashMap::new();
+        for (phase, run) in &phases {
+            let next = advance_groups(&block, &self.groups, HashMap::from([((*phase, *run), 1)]));
+            transitions.insert((*phase, *run), next.into_iter().map(|((done, next_run), count)| {
+                (phase_of(done, next_run), done - phase - period, count)
+            }).collect());
+        }
+
+        let accepting = [(0, 0), phase_of(-1, last)];
+        let useful = useful_phases(&phases,
+                                   &transitions,
+                                   start.iter().map(|((offset, run), _)| phase_of(*offset, *run)),
+                                   &accepting);
 
-        let x = Configuration::possible_configurations_h(chars,
-                                                               &groups);
-        println!("x: {:?}", x);
-        x
+        let window = finishing_offsets(&transitions, &useful, &[((0, 0), 0), (phase_of(-1, last), -1)]);
+        let states = reachable_offsets(&start, &transitions, &window, period, MAX_STATES);
+
+        Some(Unfolding {
+            period,
+            last,
+            start,
+            transitions,
+            useful,
+            states,
+        })
+    }
+
+    /* Valid fillings of the record in lexicographic order, computed lazily */
+    fn arrangements(&self) -> impl Iterator<Item = String> + '_ {
+        let mut counter = ArrangementCounter::new(&self.chars, &self.groups);
+        let total = counter.count();
+        (0..total).map(move |index| counter.unrank(index).unwrap())
+    }
+
+    /* A uniformly random valid filling, None if there is none */
+    fn sample(&self, rng: &mut Rng) -> Option<String> {
+        let mut counter = ArrangementCounter::new(&self.chars, &self.groups);
+        let total = counter.count();
+        if total == 0 {
+            return None
+        }
+        counter.unrank(rng.below(total))
+    }
+
+    /* The '?' positions that hold the same spring in every valid filling */
+    fn forced_cells(&self) -> Vec<(usize, char)> {
+        let count_with = |index: usize, c: char| {
+            let mut chars = self.chars.clone();
+            chars[index] = c;
+            ArrangementCounter::new(&chars, &self.groups).count()
+        };
+
+        (0..self.chars.len())
+            .filter(|index| self.chars[*index] == '?')
+            .filter_map(|index| {
+                match (count_with(index, '#'), count_with(index, '.')) {
+                    (0, 0) => None,
+                    (_, 0) => Some((index, '#')),
+                    (0, _) => Some((index, '.')),
+                    _ => None,
+                }
+            })
+            .collect()
+    }
+
+    fn explain(&self) -> Option<Unsatisfiable> {
+        ArrangementCounter::new(&self.chars, &self.groups).explain()
+    }
+
+    /* Tries every filling of the '?'s, only kept to cross-check the counter in debug builds */
+    fn possible_configurations_brute_force(&self) -> usize {
+        Configuration::possible_configurations_h(self.chars.clone(), &self.groups)
     }
 
     fn possible_configurations_h(chars: Vec<char>,
-                                 groups: &Vec<usize>) -> usize {
+                                 groups: &[usize]) -> usize {
 
         if !Configuration::is_still_feasible(&chars,
                                              groups) {
-            let s : String = chars.iter().collect();
-            println!("{:?} is no longer feasible", s);
             return 0
         }
 
         if Configuration::is_fixed(&chars) {
-            let s : String = chars.iter().collect();
-            println!("{:?} is feasible", s);
             return 1
         }
 
@@ -95,8 +310,8 @@ impl Configuration {
                     }).unwrap()
     }
 
-    fn is_still_feasible(chars: &Vec<char>,
-                         groups: &Vec<usize>) -> bool {
+    fn is_still_feasible(chars: &[char],
+                         groups: &[usize]) -> bool {
         let mut counts: Vec<usize> = Vec::new();
         let mut count: usize = 0;
         let mut indeterminate_state_found = false;
@@ -136,11 +351,11 @@ impl Configuration {
                 true
             }
         } else {
-            &Configuration::map_hash_groups(chars) == groups
+            Configuration::map_hash_groups(chars) == groups
         }
     }
 
-    fn map_hash_groups(chars: &Vec<char>) -> Vec<usize> {
+    fn map_hash_groups(chars: &[char]) -> Vec<usize> {
         let mut counts = Vec::new();
         let mut count = 0;
 
@@ -160,12 +375,929 @@ impl Configuration {
         counts
     }
 
-    fn is_fixed(chars: &Vec<char>) -> bool {
+    fn is_fixed(chars: &[char]) -> bool {
         chars.iter().find(|c| c == &&'?').is_none()
     }
 }
 
-fn read_input(filename: &String) ->  io::Result<Problem> {
+/* Counts the arrangements of a record with a memoized walk over
+   (position, completed groups, length of the run of '#'s we are in). */
+struct ArrangementCounter<'a> {
+    chars: &'a [char],
+    groups: &'a [usize],
+    memo: HashMap<(usize, usize, usize), u128>,
+}
+
+impl<'a> ArrangementCounter<'a> {
+    fn new(chars: &'a [char], groups: &'a [usize]) -> ArrangementCounter<'a> {
+        ArrangementCounter {
+            chars,
+            groups,
+            memo: HashMap::new(),
+        }
+    }
+
+    fn count(&mut self) -> u128 {
+        self.count_from(0, 0, 0)
+    }
+
+    /* The (group, run) after putting c down, if that still fits the groups */
+    fn step(&self, group: usize, run: usize, c: char) -> Option<(usize, usize)> {
+        if c == '#' {
+            (group < self.groups.len() && run < self.groups[group]).then_some((group, run + 1))
+        } else if run == 0 {
+            Some((group, 0))
+        } else if run == self.groups[group] {
+            Some((group + 1, 0))
+        } else {
+            None
+        }
+    }
+
+    /* The springs a position can hold, '#' first so arrangements come out in lexicographic order */
+    fn options(&self, position: usize) -> &'static [char] {
+        match self.chars[position] {
+            '#' => &['#'],
+            '.' => &['.'],
+            _ => &['#', '.'],
+        }
+    }
+
+    fn is_complete(&self, group: usize, run: usize) -> bool {
+        if run == 0 {
+            group == self.groups.len()
+        } else {
+            group + 1 == self.groups.len() && run == self.groups[group]
+        }
+    }
+
+    fn count_from(&mut self, position: usize, group: usize, run: usize) -> u128 {
+        if position == self.chars.len() {
+            return self.is_complete(group, run) as u128
+        }
+
+        if let Some(count) = self.memo.get(&(position, group, run)) {
+            return *count
+        }
+
+        let mut count = 0;
+
+        for c in self.options(position) {
+            if let Some((next_group, next_run)) = self.step(group, run, *c) {
+                count += self.count_from(position + 1, next_group, next_run);
+            }
+        }
+
+        self.memo.insert((position, group, run), count);
+        count
+    }
+
+    /* The index-th valid arrangement in lexicographic order, or None if there aren't that many */
+    fn unrank(&mut self, mut index: u128) -> Option<String> {
+        if index >= self.count() {
+            return None
+        }
+
+        let mut arrangement = String::with_capacity(self.chars.len());
+        let (mut group, mut run) = (0, 0);
+
+        for position in 0..self.chars.len() {
+            for c in self.options(position) {
+                if let Some((next_group, next_run)) = self.step(group, run, *c) {
+                    let count = self.count_from(position + 1, next_group, next_run);
+                    if index < count {
+                        arrangement.push(*c);
+                        (group, run) = (next_group, next_run);
+                        break;
+                    }
+                    index -= count;
+                }
+            }
+        }
+
+        Some(arrangement)
+    }
+
+    /* Why no arrangement exists, None when the record is satisfiable. Walks the reachable states
+       left to right and reports the first group that no prefix of the record can complete. */
+    fn explain(&mut self) -> Option<Unsatisfiable> {
+        if self.count() > 0 {
+            return None
+        }
+
+        let mut states: Vec<(usize, usize)> = vec![(0, 0)];
+        let mut placed = 0;
+
+        for position in 0..self.chars.len() {
+            let mut next_states: Vec<(usize, usize)> = Vec::new();
+            for (group, run) in &states {
+                for c in self.options(position) {
+                    if let Some(next) = self.step(*group, *run, *c) {
+                        next_states.push(next);
+                    }
+                }
+            }
+            next_states.sort();
+            next_states.dedup();
+            states = next_states;
+
+            placed = states.iter()
+                           .map(|(group, run)| {
+                               if *run > 0 && *run == self.groups[*group] { group + 1 } else { *group }
+                           })
+                           .chain([placed])
+                           .max()
+                           .unwrap();
+        }
+
+        Some(if placed < self.groups.len() {
+            Unsatisfiable::GroupDoesNotFit { group: placed + 1, size: self.groups[placed] }
+        } else {
+            Unsatisfiable::LeftoverDamage
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Unsatisfiable {
+    /* The (1-based) group can't be placed after all of the groups before it */
+    GroupDoesNotFit { group: usize, size: usize },
+    /* All groups fit, but the record has damaged springs none of them can cover */
+    LeftoverDamage,
+}
+
+/* A record cut into "separator + record" blocks, as seen by Configuration::unfolded_count */
+struct Unfolding {
+    period: i64,
+    last: usize,
+    start: Vec<((i64, usize), u128)>,
+    transitions: Transitions,
+    useful: HashSet<Phase>,
+    /* The states of the transfer matrix, None when the offset drifts without bound */
+    states: Option<Vec<(i64, usize)>>,
+}
+
+impl Unfolding {
+    fn phase_of(&self, offset: i64, run: usize) -> Phase {
+        (offset.rem_euclid(self.period), run)
+    }
+
+    /* How far one block can move the offset along a useful path, None when the matrix is used */
+    fn drift(&self) -> Option<usize> {
+        if self.states.is_some() {
+            return None
+        }
+
+        Some(self.transitions.iter()
+                             .filter(|(from, _)| self.useful.contains(from))
+                             .flat_map(|(_, next)| next.iter())
+                             .filter(|(to, _, _)| self.useful.contains(to))
+                             .map(|(_, shift, _)| shift.unsigned_abs() as usize)
+                             .max()
+                             .unwrap_or(0))
+    }
+
+    /* Roughly how many state updates counting copies blocks takes is copies^2 times this */
+    fn block_weight(&self) -> usize {
+        self.drift().map_or(0, |drift| drift.max(1) * self.transitions.len())
+    }
+
+    fn count<R: Ring>(&self, copies: usize, ring: &R) -> Result<R::Value, UnfoldError> {
+        const FIRST_TERMS: usize = 128;
+        const MAX_TERMS: usize = 1024;
+
+        let accepting = [(0, 0), (-1, self.last)];
+
+        let states = match (&self.states, ring.modulus()) {
+            (Some(states), _) => states,
+            (None, Some(modulus)) if copies > FIRST_TERMS => {
+                // Counts of drifting records follow a recurrence with coefficients polynomial in
+                // the number of copies, guessed from the first counts and checked on more of them
+                let field = Modular::new(modulus);
+                let mut terms = FIRST_TERMS;
+                while terms <= MAX_TERMS {
+                    let counts = self.block_counts(terms.min(copies), &field);
+                    if copies <= terms {
+                        return Ok(ring.lift(counts[copies - 1] as u128))
+                    }
+                    if let Some(recurrence) = Recurrence::guess(&counts, &field)? {
+                        return Ok(ring.lift(recurrence.extend(&counts, copies, &field)? as u128))
+                    }
+                    terms *= 2;
+                }
+                return Err(UnfoldError::NoRecurrence)
+            }
+            (None, _) => return Ok(self.block_counts(copies, ring).pop().unwrap()),
+        };
+
+        let index: HashMap<(i64, usize), usize> =
+            states.iter().enumerate().map(|(i, state)| (*state, i)).collect();
+
+        let mut transfer = Matrix::zero(ring, states.len());
+        for (i, (offset, run)) in states.iter().enumerate() {
+            for ((_phase, next_run), shift, count) in &self.transitions[&self.phase_of(*offset, *run)] {
+                if let Some(j) = index.get(&(offset + shift, *next_run)) {
+                    transfer.cells[i][*j] = ring.add(&transfer.cells[i][*j], &ring.lift(*count));
+                }
+            }
+        }
+
+        let mut vector = vec![ring.zero(); states.len()];
+        for (state, count) in &self.start {
+            if let Some(i) = index.get(state) {
+                vector[*i] = ring.add(&vector[*i], &ring.lift(*count));
+            }
+        }
+
+        let vector = transfer.power_applied(ring, vector, copies - 1);
+
+        Ok(accepting.iter()
+                    .filter_map(|state| index.get(state))
+                    .fold(ring.zero(), |sum, i| ring.add(&sum, &vector[*i])))
+    }
+
+    /* The counts for 1 up to copies copies, walking the offsets block by block, which takes time
+       quadratic in copies. Every block moves the offset by at most drift, so states further than
+       that from 0 than there are blocks left can never finish. */
+    fn block_counts<R: Ring>(&self, copies: usize, ring: &R) -> Vec<R::Value> {
+        let drift = self.drift().unwrap_or(0) as i64;
+        let keeps = |offset: i64, block_index: usize| offset.abs() <= drift * (copies - 1 - block_index) as i64 + 1;
+
+        // States are laid out by offset, then run, with their transitions found by phase
+        let runs = self.useful.iter().map(|(_, run)| *run).chain([self.last]).max().unwrap() + 1;
+        let bound = drift * copies as i64 + 1;
+        let index = |offset: i64, run: usize| (offset + bound) as usize * runs + run;
+        let mut steps: Vec<Vec<(usize, i64, R::Value)>> = vec![Vec::new(); self.period as usize * runs];
+        for (phase, next) in &self.transitions {
+            if self.useful.contains(phase) {
+                steps[phase.0 as usize * runs + phase.1] =
+                    next.iter()
+                        .filter(|(to, _, _)| self.useful.contains(to))
+                        .map(|((_, run), shift, count)| (*run, *shift, ring.lift(*count)))
+                        .collect();
+            }
+        }
+
+        let mut vector: Vec<R::Value> = vec![ring.zero(); (2 * bound as usize + 1) * runs];
+        let mut reach = 0;
+        for ((offset, run), count) in &self.start {
+            if self.useful.contains(&self.phase_of(*offset, *run)) && keeps(*offset, 0) {
+                vector[index(*offset, *run)] = ring.lift(*count);
+                reach = reach.max(offset.abs());
+            }
+        }
+        let finished = |vector: &Vec<R::Value>| ring.add(&vector[index(0, 0)], &vector[index(-1, self.last)]);
+
+        let mut counts = vec![finished(&vector)];
+        for block_index in 1..copies {
+            let mut next_vector: Vec<R::Value> = vec![ring.zero(); vector.len()];
+            for offset in -reach..=reach {
+                let phase = offset.rem_euclid(self.period) as usize * runs;
+                for run in 0..runs {
+                    let value = &vector[index(offset, run)];
+                    for (next_run, shift, count) in &steps[phase + run] {
+                        if keeps(offset + shift, block_index) {
+                            let entry = &mut next_vector[index(offset + shift, *next_run)];
+                            *entry = ring.add(entry, &ring.mul(value, count));
+                        }
+                    }
+                }
+            }
+            vector = next_vector;
+            reach = (reach + drift).min(bound);
+            counts.push(finished(&vector));
+        }
+        counts
+    }
+}
+
+/* A linear recurrence sum_i P_i(n) * a(n + i) = 0 for n >= 1, with polynomial coefficients:
+   polynomials[i][j] is the coefficient of n^j in P_i. Counts of a record unfolded n times are
+   the constant terms of M(x)^(n - 1) for a matrix M of Laurent polynomials in the offset x, and
+   such diagonal coefficients always satisfy one (they are holonomic). */
+struct Recurrence {
+    polynomials: Vec<Vec<u64>>,
+}
+
+impl Recurrence {
+    /* A recurrence for counts[0] = a(1), counts[1] = a(2), ... if one fits them with CHECKS
+       equations to spare. The columns n^j * a(n + i) are added one at a time, by increasing
+       degree for a few bounds on the order and by increasing order for a few bounds on the
+       degree, and the first one that depends on those before it gives a recurrence. The one
+       with the fewest terms wins, since that is about what extending costs per count. */
+    fn guess(counts: &[u64], field: &Modular) -> Result<Option<Recurrence>, UnfoldError> {
+        const CHECKS: usize = 16;
+        const MAX_ORDER: usize = 64;
+
+        if counts.len() <= MAX_ORDER + CHECKS {
+            return Ok(None)
+        }
+        let rows = counts.len() - MAX_ORDER;
+        let shapes: Vec<Vec<(usize, usize)>> =
+            [MAX_ORDER, 32, 16, 8, 4].iter()
+                                     .map(|order| (0..rows).flat_map(|j| (0..=*order).map(move |i| (i, j))).collect())
+                                     .chain([16, 8, 4, 2].iter().map(|degree| {
+                                         (0..=MAX_ORDER).flat_map(|i| (0..=*degree).map(move |j| (i, j))).collect()
+                                     }))
+                                     .collect();
+
+        let mut best: Option<Dependency> = None;
+        for columns in shapes {
+            let limit = best.as_ref().map_or(rows - CHECKS, |best| best.len() - 1);
+            if let Some(found) = first_dependency(counts, rows, &columns[..limit.min(columns.len())], field)? {
+                best = Some(found);
+            }
+        }
+
+        Ok(best.map(|terms| {
+            let order = terms.iter().map(|((i, _), _)| *i).max().unwrap();
+            let degree = terms.iter().map(|((_, j), _)| *j).max().unwrap();
+            let mut polynomials = vec![vec![0; degree + 1]; order + 1];
+            for ((i, j), c) in terms {
+                polynomials[i][j] = c;
+            }
+            Recurrence { polynomials }
+        }))
+    }
+
+    /* a(copies), going on from the known counts. The leading polynomial has to be invertible at
+       every step, and all of its values are inverted at once. The rest of the sum is taken as
+       sum_j n^j * sum_i c_ij * a(n + i), which is mostly products added up unreduced. */
+    fn extend(&self, counts: &[u64], copies: usize, field: &Modular) -> Result<u64, UnfoldError> {
+        let order = self.polynomials.len() - 1;
+        let first = counts.len() - order + 1;
+        let value = |polynomial: &[u64], n: usize| {
+            polynomial.iter().rev().fold(0, |value, c| field.add(&field.mul(&value, &field.lift(n as u128)), c))
+        };
+
+        let mut inverses: Vec<u64> = (first..=copies - order).map(|n| value(&self.polynomials[order], n)).collect();
+        invert_all(&mut inverses, field)?;
+
+        let degree = self.polynomials.iter().map(|polynomial| polynomial.len()).max().unwrap();
+        let by_degree: Vec<Vec<u64>> =
+            (0..degree).map(|j| self.polynomials[..order].iter().map(|polynomial| polynomial.get(j).copied().unwrap_or(0)).collect())
+                       .collect();
+
+        let mut counts = counts.to_vec();
+        for (n, inverse) in (first..).zip(inverses) {
+            let window = &counts[n - 1..n - 1 + order];
+            let sum = by_degree.iter().rev().fold(0, |sum, coefficients| {
+                field.add(&field.mul(&sum, &field.lift(n as u128)), &field.dot(coefficients, window))
+            });
+            counts.push(field.mul(&field.negate(sum), &inverse));
+        }
+        Ok(counts[copies - 1])
+    }
+}
+
+/* The coefficient of every column (i, j) = n^j * a(n + i) in a combination adding up to zero */
+type Dependency = Vec<((usize, usize), u64)>;
+
+/* The first of the columns n^j * a(n + i), for n = 1 to rows, that is a combination of the ones
+   before it, as the coefficients of the dependency. Every column is reduced against the earlier
+   independent ones as it comes, so stopping at c columns costs about rows * c^2. */
+fn first_dependency(counts: &[u64],
+                    rows: usize,
+                    columns: &[(usize, usize)],
+                    field: &Modular) -> Result<Option<Dependency>, UnfoldError> {
+    let mut powers: Vec<Vec<u64>> = vec![vec![1; rows]];
+    // Reduced columns, each with its pivot row and which combination of the columns it is
+    let mut basis: Vec<(usize, Vec<u64>, Vec<u64>)> = Vec::new();
+
+    for (c, (i, j)) in columns.iter().enumerate() {
+        while powers.len() <= *j {
+            let power = powers.last().unwrap().iter().enumerate().map(|(n, p)| field.mul(p, &field.lift(n as u128 + 1))).collect();
+            powers.push(power);
+        }
+        let mut column: Vec<u128> = (0..rows).map(|n| field.mul(&powers[*j][n], &counts[n + i]) as u128).collect();
+        let mut combination: Vec<u128> = vec![0; c + 1];
+        combination[c] = 1;
+
+        // Products are added up unreduced while the sums can't overflow
+        let mut unreduced = 0;
+        for (pivot, reduced, used) in &basis {
+            let factor = field.negate(field.lift(column[*pivot])) as u128;
+            if factor != 0 {
+                if unreduced == field.lazy_sums() {
+                    column.iter_mut().chain(combination.iter_mut()).for_each(|value| *value = field.lift(*value) as u128);
+                    unreduced = 0;
+                }
+                column.iter_mut().zip(reduced).for_each(|(value, r)| *value += factor * *r as u128);
+                combination.iter_mut().zip(used).for_each(|(value, u)| *value += factor * *u as u128);
+                unreduced += 1;
+            }
+        }
+        let mut column: Vec<u64> = column.into_iter().map(|value| field.lift(value)).collect();
+        let mut combination: Vec<u64> = combination.into_iter().map(|value| field.lift(value)).collect();
+
+        match column.iter().position(|value| *value != 0) {
+            None => {
+                return Ok(Some(columns.iter().zip(combination).filter(|(_, c)| *c != 0).map(|(column, c)| (*column, c)).collect()))
+            }
+            Some(pivot) => {
+                let inverse = field.inverse(column[pivot])?;
+                column.iter_mut().for_each(|value| *value = field.mul(value, &inverse));
+                combination.iter_mut().for_each(|value| *value = field.mul(value, &inverse));
+                basis.push((pivot, column, combination));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/* Replaces every value with its inverse, with a single inversion (Montgomery's trick) */
+fn invert_all(values: &mut [u64], field: &Modular) -> Result<(), UnfoldError> {
+    let mut prefix = Vec::with_capacity(values.len());
+    let mut product = 1;
+    for value in values.iter() {
+        prefix.push(product);
+        product = field.mul(&product, value);
+    }
+
+    if values.contains(&0) {
+        return Err(UnfoldError::NoRecurrence)
+    }
+    let mut inverse = field.inverse(product)?;
+    for (value, before) in values.iter_mut().zip(prefix).rev() {
+        let next = field.mul(&inverse, value);
+        *value = field.mul(&inverse, &before);
+        inverse = next;
+    }
+    Ok(())
+}
+
+/* Runs a record over (groups done, run) states. Group sizes repeat with the period of groups,
+   so done can go past the end of groups and be negative relative to some copy. */
+fn advance_groups(chars: &[char],
+                  groups: &[usize],
+                  mut states: HashMap<(i64, usize), u128>) -> HashMap<(i64, usize), u128> {
+    for c in chars {
+        let options: &[char] = match c {
+            '#' => &['#'],
+            '.' => &['.'],
+            _ => &['#', '.'],
+        };
+
+        let mut next_states: HashMap<(i64, usize), u128> = HashMap::new();
+        for ((done, run), count) in states {
+            let size = groups[done.rem_euclid(groups.len() as i64) as usize];
+            for option in options {
+                let next =
+                    if option == &'#' {
+                        (run < size).then_some((done, run + 1))
+                    } else if run == 0 {
+                        Some((done, 0))
+                    } else if run == size {
+                        Some((done + 1, 0))
+                    } else {
+                        None
+                    };
+                if let Some(next) = next {
+                    *next_states.entry(next).or_insert(0) += count;
+                }
+            }
+        }
+        states = next_states;
+    }
+    states
+}
+
+/* Every (offset, run) state a useful path can visit, if there are at most limit of them. Most
+   records pin their groups to the copies, so offsets only wobble a little around 0, and most of
+   the others only let them drift one way, which the window of finishing offsets cuts short. */
+fn reachable_offsets(start: &[((i64, usize), u128)],
+                     transitions: &Transitions,
+                     window: &HashMap<Phase, OffsetWindow>,
+                     period: i64,
+                     limit: usize) -> Option<Vec<(i64, usize)>> {
+    let phase_of = |(offset, run): (i64, usize)| (offset.rem_euclid(period), run);
+    let finishes = |(offset, run): (i64, usize)| {
+        window.get(&phase_of((offset, run))).is_some_and(|window| window.contains(offset))
+    };
+
+    let mut seen: HashSet<(i64, usize)> =
+        start.iter().map(|(state, _)| *state).filter(|state| finishes(*state)).collect();
+    let mut queue: Vec<(i64, usize)> = seen.iter().cloned().collect();
+
+    while let Some((offset, run)) = queue.pop() {
+        for ((_phase, next_run), shift, _count) in &transitions[&phase_of((offset, run))] {
+            let next = (offset + shift, *next_run);
+            if finishes(next) && seen.insert(next) {
+                if seen.len() > limit {
+                    return None
+                }
+                queue.push(next);
+            }
+        }
+    }
+
+    let mut states: Vec<(i64, usize)> = seen.into_iter().collect();
+    states.sort();
+    Some(states)
+}
+
+/* The offsets a phase can be at and still end up in an accepting state, None when unbounded */
+#[derive(Clone, Copy, Debug)]
+struct OffsetWindow {
+    low: Option<i64>,
+    high: Option<i64>,
+}
+
+impl OffsetWindow {
+    fn contains(&self, offset: i64) -> bool {
+        self.low.is_none_or(|low| low <= offset) && self.high.is_none_or(|high| offset <= high)
+    }
+}
+
+/* For every useful phase, the window of offsets from which some path of blocks ends in one of
+   the accepting (phase, offset) states. A path shifting the offset by s from offset o ends at
+   o + s, so the window is bounded by the extreme shifts, which a cycle shifting the offset in
+   the right direction makes unbounded (Bellman-Ford, relaxing as often again to spot those). */
+fn finishing_offsets(transitions: &Transitions,
+                     useful: &HashSet<Phase>,
+                     accepting: &[(Phase, i64)]) -> HashMap<Phase, OffsetWindow> {
+    let edges: Vec<(Phase, Phase, i64)> =
+        transitions.iter()
+                   .filter(|(from, _)| useful.contains(from))
+                   .flat_map(|(from, next)| next.iter().map(move |(to, shift, _)| (*from, *to, *shift)))
+                   .filter(|(_, to, _)| useful.contains(to))
+                   .collect();
+
+    // The highest of sign * (final offset - shift) over the paths, so sign 1 gives the highest offset
+    let extreme = |sign: i64| {
+        let mut best: HashMap<Phase, i64> =
+            accepting.iter().filter(|(phase, _)| useful.contains(phase)).map(|(phase, offset)| (*phase, sign * offset)).collect();
+        let mut unbounded: HashSet<Phase> = HashSet::new();
+
+        for round in 0..2 * useful.len() + 1 {
+            for (from, to, shift) in &edges {
+                if unbounded.contains(to) {
+                    unbounded.insert(*from);
+                } else if let Some(value) = best.get(to).map(|value| value - sign * shift) {
+                    if best.get(from).is_none_or(|current| value > *current) {
+                        best.insert(*from, value);
+                        if round >= useful.len() {
+                            unbounded.insert(*from);
+                        }
+                    }
+                }
+            }
+        }
+
+        move |phase: &Phase| (!unbounded.contains(phase)).then(|| sign * best[phase])
+    };
+
+    let high = extreme(1);
+    let low = extreme(-1);
+    useful.iter().map(|phase| (*phase, OffsetWindow { low: low(phase), high: high(phase) })).collect()
+}
+
+/* A phase is the group offset modulo the number of groups, with the damaged run so far */
+type Phase = (i64, usize);
+
+/* For every phase: the phase after one more block, how far it shifts the offset and in how many ways */
+type Transitions = HashMap<Phase, Vec<(Phase, i64, u128)>>;
+
+/* Phases that are reachable from the start and from which an accepting phase is reachable */
+fn useful_phases(phases: &[(i64, usize)],
+                 transitions: &Transitions,
+                 start: impl Iterator<Item = (i64, usize)>,
+                 accepting: &[(i64, usize)]) -> HashSet<(i64, usize)> {
+    let search = |roots: Vec<Phase>, edges: &dyn Fn(&Phase) -> Vec<Phase>| {
+        let mut seen: HashSet<(i64, usize)> = roots.iter().cloned().collect();
+        let mut queue: Vec<(i64, usize)> = roots;
+        while let Some(phase) = queue.pop() {
+            for next in edges(&phase) {
+                if seen.insert(next) {
+                    queue.push(next);
+                }
+            }
+        }
+        seen
+    };
+
+    let reachable = search(start.collect(), &|phase| {
+        transitions[phase].iter().map(|(next, _, _)| *next).collect()
+    });
+    let finishing = search(accepting.to_vec(), &|phase| {
+        phases.iter()
+              .filter(|from| transitions[*from].iter().any(|(next, _, _)| next == phase))
+              .cloned()
+              .collect()
+    });
+
+    reachable.intersection(&finishing).cloned().collect()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum UnfoldError {
+    /* In these records (numbered from 1) groups can drift between copies without bound, and
+       counting them exactly, block by block, would take too long for more than max_copies copies */
+    UnboundedDrift { records: Vec<usize>, copies: usize, max_copies: usize },
+    /* The counts of a drifting record fit no recurrence that its first counts pin down */
+    NoRecurrence,
+    /* Guessing a recurrence divides, which not every number has an inverse for modulo this */
+    NotPrime { modulus: u64 },
+}
+
+impl fmt::Display for UnfoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UnfoldError::UnboundedDrift { records, copies, max_copies } => {
+                let mut shown: Vec<String> = records.iter().take(10).map(|record| record.to_string()).collect();
+                if records.len() > shown.len() {
+                    shown.push("...".to_string());
+                }
+                write!(f, "the groups of {} records ({}) drift between copies, so exact counts are found block \
+                           by block and this input can only be unfolded up to {} times, not {}; try --modulo",
+                       records.len(), shown.join(", "), max_copies, copies)
+            }
+            UnfoldError::NoRecurrence =>
+                write!(f, "the counts of a record whose groups drift between copies fit no recurrence found from its first counts"),
+            UnfoldError::NotPrime { modulus } =>
+                write!(f, "records whose groups drift between copies need a prime modulus, {} is not", modulus),
+        }
+    }
+}
+
+/* The arithmetic counts are done in: either modulo some number or exact */
+trait Ring {
+    type Value: Clone;
+    fn zero(&self) -> Self::Value;
+    fn lift(&self, value: u128) -> Self::Value;
+    fn add(&self, l: &Self::Value, r: &Self::Value) -> Self::Value;
+    fn mul(&self, l: &Self::Value, r: &Self::Value) -> Self::Value;
+    /* The modulus counts are reduced by, None for exact counts */
+    fn modulus(&self) -> Option<u64>;
+}
+
+struct Modular {
+    modulus: u64,
+    /* floor((2^128 - 1) / modulus), so products reduce with multiplications (Barrett) */
+    reciprocal: u128,
+}
+
+impl Ring for Modular {
+    type Value = u64;
+
+    fn zero(&self) -> u64 {
+        0
+    }
+
+    fn lift(&self, value: u128) -> u64 {
+        self.reduce(value)
+    }
+
+    fn add(&self, l: &u64, r: &u64) -> u64 {
+        let sum = *l as u128 + *r as u128;
+        if sum >= self.modulus as u128 { (sum - self.modulus as u128) as u64 } else { sum as u64 }
+    }
+
+    fn mul(&self, l: &u64, r: &u64) -> u64 {
+        self.reduce(*l as u128 * *r as u128)
+    }
+
+    fn modulus(&self) -> Option<u64> {
+        Some(self.modulus)
+    }
+}
+
+impl Modular {
+    fn new(modulus: u64) -> Modular {
+        Modular { modulus, reciprocal: u128::MAX / modulus as u128 }
+    }
+
+    /* The quotient estimate is the top half of value * reciprocal, which is at most two short */
+    fn reduce(&self, value: u128) -> u64 {
+        let low = |x: u128| x & u64::MAX as u128;
+        let (x1, x0) = (value >> 64, low(value));
+        let (r1, r0) = (self.reciprocal >> 64, low(self.reciprocal));
+        let middle = ((x0 * r0) >> 64) + low(x1 * r0) + low(x0 * r1);
+        let quotient = x1 * r1 + ((x1 * r0) >> 64) + ((x0 * r1) >> 64) + (middle >> 64);
+
+        let mut remainder = value - quotient * self.modulus as u128;
+        while remainder >= self.modulus as u128 {
+            remainder -= self.modulus as u128;
+        }
+        remainder as u64
+    }
+
+    /* How many products of reduced values can be added to a reduced value in a u128 */
+    fn lazy_sums(&self) -> usize {
+        let largest = (self.modulus - 1) as u128;
+        ((u128::MAX - largest) / (largest * largest).max(1)).min(usize::MAX as u128) as usize
+    }
+
+    /* sum_i l_i * r_i, reducing only every lazy_sums products */
+    fn dot(&self, l: &[u64], r: &[u64]) -> u64 {
+        l.chunks(self.lazy_sums()).zip(r.chunks(self.lazy_sums())).fold(0, |sum, (l, r)| {
+            let products = l.iter().zip(r).fold(0, |sum, (l, r)| sum + *l as u128 * *r as u128);
+            self.add(&sum, &self.lift(products))
+        })
+    }
+
+    fn negate(&self, value: u64) -> u64 {
+        if value == 0 { 0 } else { self.modulus - value }
+    }
+
+    /* By the extended Euclidean algorithm, which only fails for a modulus that isn't prime */
+    fn inverse(&self, value: u64) -> Result<u64, UnfoldError> {
+        let (mut r0, mut r1) = (self.modulus as i128, value as i128);
+        let (mut s0, mut s1) = (0i128, 1i128);
+        while r1 != 0 {
+            let quotient = r0 / r1;
+            (r0, r1) = (r1, r0 - quotient * r1);
+            (s0, s1) = (s1, s0 - quotient * s1);
+        }
+        if r0 != 1 {
+            return Err(UnfoldError::NotPrime { modulus: self.modulus })
+        }
+        Ok(s0.rem_euclid(self.modulus as i128) as u64)
+    }
+}
+
+struct Exact;
+
+impl Ring for Exact {
+    type Value = BigUint;
+
+    fn zero(&self) -> BigUint {
+        BigUint::from_u128(0)
+    }
+
+    fn lift(&self, value: u128) -> BigUint {
+        BigUint::from_u128(value)
+    }
+
+    fn add(&self, l: &BigUint, r: &BigUint) -> BigUint {
+        l.add(r)
+    }
+
+    fn mul(&self, l: &BigUint, r: &BigUint) -> BigUint {
+        l.mul(r)
+    }
+
+    fn modulus(&self) -> Option<u64> {
+        None
+    }
+}
+
+/* Just enough of an unsigned big integer to count with: base 2^32 limbs, least significant first */
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct BigUint {
+    limbs: Vec<u32>,
+}
+
+impl BigUint {
+    fn from_u128(mut value: u128) -> BigUint {
+        let mut limbs = Vec::new();
+        while value > 0 {
+            limbs.push(value as u32);
+            value >>= 32;
+        }
+        BigUint { limbs }
+    }
+
+    fn add(&self, other: &BigUint) -> BigUint {
+        let mut limbs = Vec::with_capacity(self.limbs.len().max(other.limbs.len()) + 1);
+        let mut carry = 0u64;
+        for i in 0..self.limbs.len().max(other.limbs.len()) {
+            let sum = *self.limbs.get(i).unwrap_or(&0) as u64
+                + *other.limbs.get(i).unwrap_or(&0) as u64
+                + carry;
+            limbs.push(sum as u32);
+            carry = sum >> 32;
+        }
+        if carry > 0 {
+            limbs.push(carry as u32);
+        }
+        BigUint { limbs }
+    }
+
+    fn mul(&self, other: &BigUint) -> BigUint {
+        if self.limbs.is_empty() || other.limbs.is_empty() {
+            return BigUint { limbs: Vec::new() }
+        }
+
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, l) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, r) in other.limbs.iter().enumerate() {
+                let product = *l as u64 * *r as u64 + limbs[i + j] as u64 + carry;
+                limbs[i + j] = product as u32;
+                carry = product >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigUint { limbs }
+    }
+}
+
+impl fmt::Display for BigUint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Peel off nine decimal digits at a time by dividing by 10^9
+        let mut limbs = self.limbs.clone();
+        let mut chunks: Vec<u32> = Vec::new();
+        while !limbs.is_empty() {
+            let mut remainder = 0u64;
+            for limb in limbs.iter_mut().rev() {
+                let value = (remainder << 32) | *limb as u64;
+                *limb = (value / 1_000_000_000) as u32;
+                remainder = value % 1_000_000_000;
+            }
+            chunks.push(remainder as u32);
+            while limbs.last() == Some(&0) {
+                limbs.pop();
+            }
+        }
+
+        match chunks.split_last() {
+            None => write!(f, "0"),
+            Some((most_significant, rest)) => {
+                write!(f, "{}", most_significant)?;
+                for chunk in rest.iter().rev() {
+                    write!(f, "{:09}", chunk)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+struct Matrix<V> {
+    cells: Vec<Vec<V>>,
+}
+
+impl<V: Clone> Matrix<V> {
+    fn zero<R: Ring<Value = V>>(ring: &R, size: usize) -> Matrix<V> {
+        Matrix { cells: vec![vec![ring.zero(); size]; size] }
+    }
+
+    fn mul<R: Ring<Value = V>>(&self, ring: &R, other: &Matrix<V>) -> Matrix<V> {
+        let size = self.cells.len();
+        let mut product = Matrix::zero(ring, size);
+        for i in 0..size {
+            for k in 0..size {
+                for j in 0..size {
+                    product.cells[i][j] =
+                        ring.add(&product.cells[i][j], &ring.mul(&self.cells[i][k], &other.cells[k][j]));
+                }
+            }
+        }
+        product
+    }
+
+    fn apply<R: Ring<Value = V>>(&self, ring: &R, vector: &[V]) -> Vec<V> {
+        (0..vector.len()).map(|j| {
+            (0..vector.len()).fold(ring.zero(), |sum, i| {
+                ring.add(&sum, &ring.mul(&vector[i], &self.cells[i][j]))
+            })
+        }).collect()
+    }
+
+    /* vector * self^exponent, by repeated squaring */
+    fn power_applied<R: Ring<Value = V>>(self, ring: &R, mut vector: Vec<V>, mut exponent: usize) -> Vec<V> {
+        let mut square = self;
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                vector = square.apply(ring, &vector);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                square = square.mul(ring, &square);
+            }
+        }
+        vector
+    }
+}
+
+/* Picks the ranks for --sample; draws are 128 bits wide since arrangement counts are u128 */
+struct Rng {
+    state: u64
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: u128) -> u128 {
+        let value = ((self.next_u64() as u128) << 64) | self.next_u64() as u128;
+        value % n
+    }
+}
+
+fn read_input(filename: &str) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;
 
     let configurations: Vec<Configuration> =
@@ -187,3 +1319,53 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
         configurations
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(line: &str) -> Configuration {
+        let (chars, groups) = line.split_once(' ').unwrap();
+        Configuration {
+            chars: chars.chars().collect(),
+            groups: groups.split(',').map(|group| group.parse().unwrap()).collect(),
+        }
+    }
+
+    /* The sample, and records whose groups drift between copies ("??? 1" holds 0 to 2 groups a copy) */
+    const RECORDS: [&str; 10] = ["???.### 1,1,3", ".??..??...?##. 1,1,3", "?#?#?#?#?#?#?#? 1,3,1,6",
+                                 "????.#...#... 4,1,1", "????.######..#####. 1,6,5", "?###???????? 3,2,1",
+                                 "??? 1", "?.?? 1,1", "??#?? 2,1", "#?.? 1"];
+
+    #[test]
+    fn unfolded_count_matches_the_unfolded_record() {
+        let field = Modular::new(1000000007);
+        for line in RECORDS {
+            let x = record(line);
+            for separator in ['?', '.', '#'] {
+                for copies in 1..=5 {
+                    let count = x.possible_fixed_configurations(copies, separator);
+                    assert_eq!(x.unfolded_count(copies, separator, &Exact),
+                               Ok(BigUint::from_u128(count)),
+                               "{} unfolded {} times with '{}'", line, copies, separator);
+                    assert_eq!(x.unfolded_count(copies, separator, &field),
+                               Ok((count % 1000000007) as u64),
+                               "{} unfolded {} times with '{}' modulo 1000000007", line, copies, separator);
+                }
+            }
+        }
+    }
+
+    /* "??? 1" unfolded n times is 4n - 1 unknown springs holding n groups of 1, which fit in
+       C(3n, n) ways; past the first counts those come from the guessed recurrence */
+    #[test]
+    fn drifting_counts_follow_their_recurrence() {
+        let field = Modular::new(1000000007);
+        let factorial = |n: usize| (1..=n as u128).fold(1, |product, i| field.mul(&product, &field.lift(i)));
+        for copies in [200, 1000, 5000] {
+            let binomial = field.mul(&factorial(3 * copies),
+                                     &field.inverse(field.mul(&factorial(copies), &factorial(2 * copies))).unwrap());
+            assert_eq!(record("??? 1").unfolded_count(copies, '?', &field), Ok(binomial));
+        }
+    }
+}
@@ -1,171 +1,350 @@
 extern crate core;
 
 use std::fs::File;
-use std::io::{self, BufReader, BufRead};
+use std::io::{self, BufReader, BufRead, BufWriter, Write};
 use std::env;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let input = &args[1];
 
-    let mut problem = read_input(input)?;
+    let problem = read_input(input)?;
 
-    println!("What number do you get after summarizing all of your notes? {:?}",
-            solve1(&problem));
+    let (sum, unclear) = solve1(&problem);
+    println!("What number do you get after summarizing all of your notes? {:?}", sum);
+    report(&unclear);
 
+    let (sum, unclear) = solve2(&problem);
     println!("What number do you get after summarizing the new reflection line in each pattern in your notes? {:?}",
-            solve2(&mut problem));
+            sum);
+    report(&unclear);
+
+    if let Some(i) = args.iter().position(|arg| arg == "--smudges") {
+        let smudges: u32 = args[i + 1].parse().expect("--smudges takes a number");
+        let (sum, unclear) = summarize(&problem, smudges);
+        println!("Summarizing the reflection lines with exactly {} smudges gives {}", smudges, sum);
+        report(&unclear);
+    }
 
-    Ok(())
-}
+    if args.iter().any(|arg| arg == "--reflections") {
+        for (index, pattern) in problem.patterns.iter().enumerate() {
+            println!("Pattern {}: {:?} then {:?}", index, pattern.reflection(0), pattern.reflection(1));
+        }
+    }
+
+    let render_smudges = args.iter()
+                             .position(|arg| arg == "--smudges")
+                             .map_or(1, |i| args[i + 1].parse().unwrap());
+
+    if args.iter().any(|arg| arg == "--render") {
+        for (index, pattern) in problem.patterns.iter().enumerate() {
+            let reflection = pattern.reflection_to_render(render_smudges);
+            println!("Pattern {}: {:?}", index, reflection);
+            print!("{}", pattern.render_ansi(reflection.as_ref()));
+            println!();
+        }
+    }
 
-fn solve2(problem: &mut Problem) -> usize {
-    problem.patterns.iter()
-                    .map(|pattern| {
-                        let row = pattern.get_mirror_row();
-                        let column =pattern.get_mirror_column();
+    if let Some(i) = args.iter().position(|arg| arg == "--render-html") {
+        let mut out = BufWriter::new(File::create(&args[i + 1])?);
+        write_html(&problem, render_smudges, &mut out)?;
+    }
 
-                        for x in 0..pattern.width() {
-                            for y in 0..pattern.height() {
-                                let other_pattern = pattern.flip(x, y);
-                                let other_row = other_pattern.get_mirror_row_excluding(&row);
-                                let other_column =other_pattern.get_mirror_column_excluding(&column);
+    if args.iter().any(|arg| arg == "--mismatches") {
+        for (index, pattern) in problem.patterns.iter().enumerate() {
+            println!("Pattern {}: horizontal {:?}, vertical {:?}",
+                     index,
+                     pattern.mismatches(Axis::Horizontal),
+                     pattern.mismatches(Axis::Vertical));
+        }
+    }
 
-                                if other_row.is_some()
-                                   || other_column.is_some() {
-                                    let row_value =  if row != other_row { other_row.unwrap_or(0) } else { 0};
-                                    let column_value = if column != other_column { other_column.unwrap_or(0) } else {0};
-                                    return row_value + (column_value * 100)
-                                }
-                            }
-                        }
-                        panic!("Unexpected state");
-                    }).sum()
+    Ok(())
 }
 
-fn solve1(problem: &Problem) -> usize {
-    problem.patterns.iter()
-                     .map(|pattern| {
-                         let row = pattern.get_mirror_row().unwrap_or(0);
-                         let column =pattern.get_mirror_column().unwrap_or(0) * 100;
-                         row + column
-                     }).sum()
+fn report(unclear: &[(usize, ReflectionError)]) {
+    for (index, error) in unclear {
+        println!("  Pattern {} was left out: {:?}", index, error);
+    }
 }
 
-struct Problem {
-    patterns: Vec<Pattern>
+fn solve2(problem: &Problem) -> (usize, Vec<(usize, ReflectionError)>) {
+    summarize(problem, 1)
 }
 
-#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Hash, Debug)]
-struct Pattern {
-    map: Vec<Vec<char>>
+fn solve1(problem: &Problem) -> (usize, Vec<(usize, ReflectionError)>) {
+    summarize(problem, 0)
 }
 
-impl Pattern {
+/* Sums the reflection of every pattern that needs exactly smudges cells fixed, together with the
+   patterns that were left out because they have no such reflection or more than one */
+fn summarize(problem: &Problem, smudges: u32) -> (usize, Vec<(usize, ReflectionError)>) {
+    let mut sum = 0;
+    let mut unclear = Vec::new();
 
-    fn is_mirror_column(&self, line: f32) -> bool {
+    for (index, pattern) in problem.patterns.iter().enumerate() {
+        match pattern.reflection(smudges) {
+            Ok(reflection) => sum += reflection.summary(),
+            Err(error) => unclear.push((index, error))
+        }
+    }
 
+    (sum, unclear)
+}
 
-        for y in 0..self.height() {
-            let mut left_index = line.floor() as i32;
-            let mut right_index = line.ceil()  as i32;
-            let row = self.map.get(y).unwrap();
+struct Problem {
+    patterns: Vec<Pattern>
+}
 
-            while left_index >= 0
-                  && right_index < (self.width() as i32) {
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Axis {
+    /* A mirror line between two rows */
+    Horizontal,
+    /* A mirror line between two columns */
+    Vertical
+}
 
-                let l = row.get(left_index as usize).unwrap();
-                let r = row.get(right_index as usize).unwrap();
-                if l != r {
-                    return false
-                }
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+struct Point {
+    x: usize,
+    y: usize
+}
 
-                left_index -= 1;
-                right_index += 1;
-            }
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Reflection {
+    axis: Axis,
+    /* The number of rows above or columns left of the mirror line */
+    position: usize,
+    /* The cell to fix when the reflection is off by exactly one cell */
+    smudge: Option<Point>
+}
+
+impl Reflection {
+    fn summary(&self) -> usize {
+        match self.axis {
+            Axis::Vertical => self.position,
+            Axis::Horizontal => self.position * 100
         }
-        true
     }
+}
 
-    fn is_mirror_row(&self, line: f32) -> bool {
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum ReflectionError {
+    NoReflection,
+    Ambiguous(Vec<Reflection>)
+}
 
-        let mut left_index = line.floor() as i32;
-        let mut right_index = line.ceil()  as i32;
+/* Every row and every column of a pattern as a bitmask, with a set bit for each '#' */
+#[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Hash, Debug)]
+struct Pattern {
+    rows: Vec<u64>,
+    columns: Vec<u64>
+}
 
-        while left_index >= 0
-            && right_index < (self.height() as i32) {
+impl Pattern {
 
-            let lrow = self.map.get(left_index as usize).unwrap();
-            let rrow = self.map.get(right_index as usize).unwrap();
+    fn new(map: &[Vec<char>]) -> Self {
+        let bits = |cells: &mut dyn Iterator<Item = char>| {
+            cells.enumerate()
+                 .filter(|(_, c)| *c == '#')
+                 .fold(0u64, |mask, (i, _)| mask | (1 << i))
+        };
 
-            for x in 0..self.width() {
-                let l = lrow.get(x).unwrap();
-                let r = rrow.get(x).unwrap();
-                if l != r {
-                    return false
-                }
-            }
+        Pattern {
+            rows: map.iter().map(|row| bits(&mut row.iter().cloned())).collect(),
+            columns: (0..map[0].len()).map(|x| bits(&mut map.iter().map(|row| row[x]))).collect()
+        }
+    }
 
-            left_index -= 1;
-            right_index += 1;
+    /* The lines that get mirrored onto each other by a mirror line along the axis */
+    fn lines(&self, axis: Axis) -> &[u64] {
+        match axis {
+            Axis::Horizontal => &self.rows,
+            Axis::Vertical => &self.columns
         }
+    }
 
-        true
+    /* The pairs of lines mirrored onto each other across the mirror line at position */
+    fn mirrored_pairs(&self, axis: Axis, position: usize) -> impl Iterator<Item = (usize, usize)> {
+        let count = self.lines(axis).len();
+        (0..position).rev().zip(position..count)
     }
 
-    fn flip(&self, x: usize, y: usize) -> Self {
-        let mut new_map: Vec<Vec<char>> = self.map.clone();
-        let  row = new_map.get_mut(y).unwrap();
-        row[x] = if self.map.get(y).unwrap().get(x).unwrap() == &'#' { '.' } else { '#' };
-        Pattern {
-            map: new_map
-        }
+    /* For every mirror line along the axis: its position and the number of cells that differ
+       from their mirror image across it */
+    fn mismatches(&self, axis: Axis) -> Vec<(usize, u32)> {
+        let lines = self.lines(axis);
+        (1..lines.len()).map(|position| {
+            let mismatches = self.mirrored_pairs(axis, position)
+                                 .map(|(l, r)| (lines[l] ^ lines[r]).count_ones())
+                                 .sum();
+            (position, mismatches)
+        }).collect()
     }
 
-    fn get_mirror_row(&self) -> Option<usize> {
-            (0..(self.width() - 1))
-                .find(|i| self.is_mirror_column((*i as f32)  + 0.5))
-                .map(|i| i + 1)
+    /* The single differing cell across a mirror line, taken on the side before the line */
+    fn smudge(&self, axis: Axis, position: usize) -> Option<Point> {
+        let lines = self.lines(axis);
+        let (line, difference) = self.mirrored_pairs(axis, position)
+                                     .map(|(l, r)| (l, lines[l] ^ lines[r]))
+                                     .find(|(_, difference)| *difference != 0)?;
+        let across = difference.trailing_zeros() as usize;
+
+        Some(match axis {
+            Axis::Horizontal => Point { x: across, y: line },
+            Axis::Vertical => Point { x: line, y: across }
+        })
     }
 
-    fn get_mirror_row_excluding(&self, exclusion: &Option<usize>) -> Option<usize> {
+    /* Every mirror line that needs exactly smudges cells fixed */
+    fn reflections(&self, smudges: u32) -> Vec<Reflection> {
+        [Axis::Horizontal, Axis::Vertical].into_iter().flat_map(|axis| {
+            self.mismatches(axis)
+                .into_iter()
+                .filter(|(_, mismatches)| *mismatches == smudges)
+                .map(move |(position, _)| Reflection {
+                    axis,
+                    position,
+                    smudge: if smudges == 1 { self.smudge(axis, position) } else { None }
+                })
+        }).collect()
+    }
 
-        if exclusion.is_none() {
-            return self.get_mirror_row()
+    fn reflection(&self, smudges: u32) -> Result<Reflection, ReflectionError> {
+        let mut reflections = self.reflections(smudges);
+        match reflections.len() {
+            0 => Err(ReflectionError::NoReflection),
+            1 => Ok(reflections.remove(0)),
+            _ => Err(ReflectionError::Ambiguous(reflections))
         }
+    }
 
-        let exclusion = exclusion.unwrap();
-
-        (0..(self.width() - 1))
-            .find(|i| i + 1 != exclusion && self.is_mirror_column((*i as f32)  + 0.5))
-            .map(|i| i + 1)
+    /* The reflection with that many smudges, or else the one without any */
+    fn reflection_to_render(&self, smudges: u32) -> Option<Reflection> {
+        self.reflection(smudges).or_else(|_| self.reflection(0)).ok()
     }
 
-    fn get_mirror_column(&self) -> Option<usize> {
-        (0..(self.height() - 1)).find(|i| self.is_mirror_row((*i as f32) + 0.5))
-                                .map(|i| i+1 )
+    fn width(&self) -> usize {
+        self.columns.len()
     }
 
-    fn get_mirror_column_excluding(&self, exclusion: &Option<usize>) -> Option<usize> {
+    /* The pattern cell by cell with the mirror line drawn in: cells that have a mirror image
+       across it are Mirrored, the ones beyond the shorter half Unmatched. */
+    fn layout(&self, reflection: Option<&Reflection>) -> Vec<Vec<(char, CellKind)>> {
+        let cell = |x: usize, y: usize| {
+            let c = if self.rows[y] >> x & 1 == 1 { '#' } else { '.' };
+            let kind = match reflection {
+                None => CellKind::Plain,
+                Some(reflection) if reflection.smudge == Some(Point { x, y }) => CellKind::Smudge,
+                Some(reflection) => {
+                    let (index, count) = match reflection.axis {
+                        Axis::Horizontal => (y, self.rows.len()),
+                        Axis::Vertical => (x, self.width())
+                    };
+                    let reach = reflection.position.min(count - reflection.position);
+                    if index + reach >= reflection.position && index < reflection.position + reach {
+                        CellKind::Mirrored
+                    } else {
+                        CellKind::Unmatched
+                    }
+                }
+            };
+            (c, kind)
+        };
+
+        let mut layout: Vec<Vec<(char, CellKind)>> = Vec::new();
+        for y in 0..self.rows.len() {
+            if let Some(Reflection { axis: Axis::Horizontal, position, .. }) = reflection {
+                if *position == y {
+                    layout.push(vec![('─', CellKind::Marker); self.width()]);
+                }
+            }
 
-        if exclusion.is_none() {
-            return self.get_mirror_column()
+            let mut row = Vec::new();
+            for x in 0..self.width() {
+                if let Some(Reflection { axis: Axis::Vertical, position, .. }) = reflection {
+                    if *position == x {
+                        row.push(('│', CellKind::Marker));
+                    }
+                }
+                row.push(cell(x, y));
+            }
+            layout.push(row);
         }
 
-        let exclusion = exclusion.unwrap();
-
-        (0..(self.height() - 1))
-            .find(|i| i + 1 != exclusion && self.is_mirror_row((*i as f32) + 0.5))
-            .map(|i| i+1 )
+        layout
     }
 
-    fn width(&self) -> usize {
-        self.map.get(0).unwrap().len()
+    /* The pattern with the mirror line in yellow, the smudge on a red background and the
+       cells without a mirror image dimmed */
+    fn render_ansi(&self, reflection: Option<&Reflection>) -> String {
+        const RESET: &str = "\x1b[0m";
+
+        let mut rendered = String::new();
+
+        for row in self.layout(reflection) {
+            for (c, kind) in row {
+                let style = match kind {
+                    CellKind::Plain | CellKind::Mirrored => "",
+                    CellKind::Unmatched => "\x1b[2m",
+                    CellKind::Smudge => "\x1b[1;41;97m",
+                    CellKind::Marker => "\x1b[1;93m",
+                };
+                if style.is_empty() {
+                    rendered.push(c);
+                } else {
+                    rendered.push_str(style);
+                    rendered.push(c);
+                    rendered.push_str(RESET);
+                }
+            }
+            rendered.push('\n');
+        }
+
+        rendered
     }
+}
 
-    fn height(&self) -> usize {
-        self.map.len()
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CellKind {
+    /* A cell of a pattern without a reflection */
+    Plain,
+    Mirrored,
+    Unmatched,
+    Smudge,
+    Marker,
+}
+
+/* All patterns as one HTML page, styled the same way as the terminal rendering */
+fn write_html(problem: &Problem, smudges: u32, out: &mut impl Write) -> io::Result<()> {
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html><head><meta charset=\"utf-8\"><title>Point of Incidence</title><style>")?;
+    writeln!(out, "body {{ background: #111; color: #ddd; font-family: monospace; }}")?;
+    writeln!(out, "pre {{ display: inline-block; margin: 1em; vertical-align: top; }}")?;
+    writeln!(out, ".unmatched {{ color: #555; }}")?;
+    writeln!(out, ".smudge {{ background: #c00; color: #fff; font-weight: bold; }}")?;
+    writeln!(out, ".marker {{ color: #fd3; font-weight: bold; }}")?;
+    writeln!(out, "</style></head><body>")?;
+
+    for (index, pattern) in problem.patterns.iter().enumerate() {
+        let reflection = pattern.reflection_to_render(smudges);
+        writeln!(out, "<pre title=\"{:?}\">Pattern {}", reflection, index)?;
+        for row in pattern.layout(reflection.as_ref()) {
+            for (c, kind) in row {
+                match kind {
+                    CellKind::Plain | CellKind::Mirrored => write!(out, "{}", c)?,
+                    CellKind::Unmatched => write!(out, "<span class=\"unmatched\">{}</span>", c)?,
+                    CellKind::Smudge => write!(out, "<span class=\"smudge\">{}</span>", c)?,
+                    CellKind::Marker => write!(out, "<span class=\"marker\">{}</span>", c)?,
+                }
+            }
+            writeln!(out)?;
+        }
+        writeln!(out, "</pre>")?;
     }
+
+    writeln!(out, "</body></html>")?;
+    out.flush()
 }
 
 
@@ -179,18 +358,22 @@ fn read_input(filename: &String) ->  io::Result<Problem> {
     for line in BufReader::new(file_in).lines().map(|x| { x.unwrap() }) {
 
         if line.is_empty() {
-            patterns.push(Pattern {
-                map: map.clone()
-            });
-            map.clear();
+            // Several blank lines in a row, or one at the end, don't make an empty pattern
+            if !map.is_empty() {
+                patterns.push(Pattern::new(&map));
+                map.clear();
+            }
+        } else if line.len() > 64 || map.len() == 64 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                      "patterns larger than 64 by 64 cells are not supported"))
         } else {
             map.push(line.chars().collect())
         }
     }
 
-    patterns.push(Pattern {
-        map: map.clone()
-    });
+    if !map.is_empty() {
+        patterns.push(Pattern::new(&map));
+    }
 
     Ok(Problem {
         patterns
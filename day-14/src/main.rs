@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
 use std::env;
+use std::fmt;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
@@ -15,41 +16,218 @@ fn main() -> io::Result<()> {
     println!("Afterwards, what is the total load on the north support beams?, {:?}",
              solve2(&mut problem.clone()));
 
+    let edge = match args.iter().position(|arg| arg == "--edge") {
+        Some(i) => Direction::parse(&args[i + 1]).expect("--edge takes N, W, S or E"),
+        None => Direction::North,
+    };
+
+    let plan = if let Some(i) = args.iter().position(|arg| arg == "--tilt") {
+        Some(Plan::parse(&args[i + 1]).unwrap_or_else(|error| panic!("Invalid --tilt: {:?}", error)))
+    } else if let Some(i) = args.iter().position(|arg| arg == "--random") {
+        let length: usize = args[i + 1].parse().expect("--random takes a number of tilts");
+        let seed: u64 = args.iter()
+                            .position(|arg| arg == "--seed")
+                            .map_or(2023, |i| args[i + 1].parse().expect("--seed takes a number"));
+        Some(Plan::random(length, &mut Rng::new(seed)))
+    } else {
+        args.iter().position(|arg| arg == "--cycles").map(|i| Plan {
+            tilts: Plan::CYCLE.to_vec(),
+            repeat: args[i + 1].parse().expect("--cycles takes a number"),
+        })
+    };
+
+    if let Some(plan) = plan {
+        let spin = spin(&mut problem.clone(), &plan, edge);
+        match spin.period {
+            Some(period) => println!("After {} the load on the {:?} edge is {}: the dish repeats every {} rounds after the first {}",
+                                     plan, edge, spin.load, period, spin.prefix),
+            None => println!("After {} the load on the {:?} edge is {}: no state repeated",
+                             plan, edge, spin.load),
+        }
+    } else if edge != Direction::North {
+        println!("The load on the {:?} edge is {}", edge, problem.total_load(edge));
+    }
+
     Ok(())
 }
 
 fn solve1(problem: &mut Problem) -> usize {
-    let north = Point { x: 0, y: -1 };
-    problem.step(&north);
-    problem.total_load()
+    problem.step(Direction::North);
+    problem.total_load(Direction::North)
+}
+
+fn solve2(problem: &mut Problem) -> usize {
+    let plan = Plan { tilts: Plan::CYCLE.to_vec(), repeat: 1000000000 };
+    spin(problem, &plan, Direction::North).load
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+enum Direction {
+    North,
+    West,
+    South,
+    East,
+}
+
+impl Direction {
+    fn parse(text: &str) -> Option<Direction> {
+        match text.to_ascii_lowercase().as_str() {
+            "n" | "north" => Some(Direction::North),
+            "w" | "west" => Some(Direction::West),
+            "s" | "south" => Some(Direction::South),
+            "e" | "east" => Some(Direction::East),
+            _ => None,
+        }
+    }
+
+    fn to_char(self) -> char {
+        match self {
+            Direction::North => 'N',
+            Direction::West => 'W',
+            Direction::South => 'S',
+            Direction::East => 'E',
+        }
+    }
+
+    fn is_vertical(self) -> bool {
+        matches!(self, Direction::North | Direction::South)
+    }
+
+    /* The cell at distance along from this edge, on the line at across: a column for north
+       and south, a row for west and east */
+    fn cell(self, across: usize, along: usize, width: usize, height: usize) -> Point {
+        let (x, y) = match self {
+            Direction::North => (across, along),
+            Direction::South => (across, height - 1 - along),
+            Direction::West => (along, across),
+            Direction::East => (width - 1 - along, across),
+        };
+        Point { x: x as i32, y: y as i32 }
+    }
 }
 
-fn solve2(problem: &mut  Problem) -> usize {
-    let total_steps = 1000000000;
+/* A sequence of tilts, repeated a number of times. Written as directions separated by spaces,
+   optionally followed by "x" and the number of rounds, e.g. "N W S E x 1e9" or "N N E". */
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Plan {
+    tilts: Vec<Direction>,
+    repeat: usize,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum PlanError {
+    Empty,
+    UnknownDirection(String),
+    InvalidCount(String),
+    TrailingInput(String),
+}
+
+impl Plan {
+    const CYCLE: [Direction; 4] = [Direction::North, Direction::West, Direction::South, Direction::East];
+
+    fn parse(text: &str) -> Result<Plan, PlanError> {
+        let mut tokens = text.split_whitespace();
+        let mut tilts = Vec::new();
+        let mut repeat = 1;
+
+        while let Some(token) = tokens.next() {
+            if token.eq_ignore_ascii_case("x") {
+                let count = tokens.next().unwrap_or("");
+                repeat = parse_count(count).ok_or_else(|| PlanError::InvalidCount(count.to_string()))?;
+                if let Some(rest) = tokens.next() {
+                    return Err(PlanError::TrailingInput(rest.to_string()))
+                }
+            } else {
+                tilts.push(Direction::parse(token)
+                    .ok_or_else(|| PlanError::UnknownDirection(token.to_string()))?);
+            }
+        }
 
-    let mut step_till_cycle: HashMap<Problem, usize> = HashMap::new();
+        if tilts.is_empty() {
+            return Err(PlanError::Empty)
+        }
 
-    for step in 1..total_steps + 1 {
+        Ok(Plan { tilts, repeat })
+    }
 
-        problem.cycle();
+    fn random(length: usize, rng: &mut Rng) -> Plan {
+        Plan {
+            tilts: (0..length).map(|_| Plan::CYCLE[rng.below(4)]).collect(),
+            repeat: 1,
+        }
+    }
+}
 
-        if step_till_cycle.contains_key(&problem) {
-            let previous_steps = step_till_cycle.get(&problem).unwrap();
-            let steps_till_equalibrium = step - previous_steps;
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tilts: Vec<String> = self.tilts.iter().map(|tilt| tilt.to_char().to_string()).collect();
+        write!(f, "{} x {}", tilts.join(" "), self.repeat)
+    }
+}
 
-            let remaining_steps = 1000000000 - step;
-            let remainder_of_remaining_step = remaining_steps % steps_till_equalibrium;
+/* A count like 1000, 1_000 or 1e9 */
+fn parse_count(text: &str) -> Option<usize> {
+    let text = text.replace('_', "");
+    match text.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => {
+            let mantissa: usize = mantissa.parse().ok()?;
+            mantissa.checked_mul(10usize.checked_pow(exponent.parse().ok()?)?)
+        }
+        None => text.parse().ok(),
+    }
+}
 
-            (0..remainder_of_remaining_step).for_each(|_|problem.cycle());
+struct Spin {
+    /* The load on the edge after all rounds of the plan */
+    load: usize,
+    /* The number of rounds before the dish first reaches a state it returns to */
+    prefix: usize,
+    /* How many rounds it takes to return to that state, if it was seen before the end */
+    period: Option<usize>,
+}
 
-            return problem.total_load()
+/* Runs rounds of the plan until a state repeats, then skips ahead to the end. States are looked up
+   by fingerprint and confirmed against the rock positions, so a collision can't fake a cycle. */
+fn spin(problem: &mut Problem, plan: &Plan, edge: Direction) -> Spin {
+    let target = plan.repeat;
+
+    let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+    let mut states: Vec<Vec<u64>> = Vec::new();
+    let mut loads: Vec<usize> = Vec::new();
+
+    for step in 0..target {
+        let state = problem.rounded_rocks();
+        let candidates = seen.entry(fingerprint(&state)).or_default();
+
+        if let Some(previous) = candidates.iter().find(|previous| states[**previous] == state) {
+            let period = step - previous;
+            return Spin {
+                load: loads[previous + (target - previous) % period],
+                prefix: *previous,
+                period: Some(period),
+            }
         }
 
-        step_till_cycle.insert(problem.clone(), step);
+        candidates.push(step);
+        states.push(state);
+        loads.push(problem.total_load(edge));
+        for tilt in &plan.tilts {
+            problem.step(*tilt);
+        }
+    }
 
+    Spin {
+        load: problem.total_load(edge),
+        prefix: target,
+        period: None,
     }
+}
 
-    problem.total_load()
+/* 64-bit FNV-1a over the words of a state */
+fn fingerprint(state: &[u64]) -> u64 {
+    state.iter()
+         .flat_map(|word| word.to_le_bytes())
+         .fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
 }
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Hash, Debug)]
@@ -63,24 +241,12 @@ impl Problem {
     }
 
     fn width(&self) -> usize {
-        self.maps.get(0).unwrap().len()
+        self.maps.first().unwrap().len()
     }
 
     fn char_at(&self, point: &Point) -> char {
         let x: &Vec<char> = self.maps.get(point.y as usize).unwrap();
-        x.get(point.x as usize).unwrap( ).clone()
-    }
-
-    fn is_on_map(&self, point: &Point) -> bool {
-        point.x >= 0
-        && (point.x as usize) < self.width()
-        && point.y >= 0
-        && (point.y as usize) < self.height()
-    }
-
-    fn is_free(&self,
-               point: &Point) -> bool {
-        self.is_on_map(point) && self.char_at(point) == '.'
+        x[point.x as usize]
     }
 
     fn set_chat_at_position(&mut self,
@@ -90,98 +256,62 @@ impl Problem {
         row[point.x as usize] = value
     }
 
-    fn total_load(&self) -> usize {
+    /* Every rounded rock weighs as much as its distance from the opposite edge, counting the
+       cell next to that edge as one */
+    fn total_load(&self, edge: Direction) -> usize {
         self.maps.iter()
                  .enumerate()
-                 .map(|(index, line)|{
-                     line.iter().map(|c| if c == &'O' { self.height() - index  } else { 0 })
-                                .sum::<usize>()
+                 .map(|(y, line)|{
+                     line.iter().enumerate().map(|(x, c)| if c == &'O' {
+                         match edge {
+                             Direction::North => self.height() - y,
+                             Direction::South => y + 1,
+                             Direction::West => self.width() - x,
+                             Direction::East => x + 1,
+                         }
+                     } else { 0 }).sum::<usize>()
                   }).sum::<usize>()
     }
 
-    fn handle_step(&mut self,
-                   position: &Point,
-                   direction: &Point) {
-
-        if self.char_at(&position) != 'O' {
-            return;
-        }
-
-        let mut next_postion = position.clone();
-
-        loop {
-            let test = next_postion.add(direction);
-            if !self.is_free(&test) {
-                break;
+    /* Slides the rounded rocks along a line of cells towards its first cell. Every stretch
+       between cube rocks is rewritten in one go: its rounded rocks first, then empty space. */
+    fn slide(&mut self, line: &[Point]) {
+        let segments: Vec<&[Point]> = line.split(|point| self.char_at(point) == '#').collect();
+        for segment in segments {
+            let rounded = segment.iter().filter(|point| self.char_at(point) == 'O').count();
+            for (index, point) in segment.iter().enumerate() {
+                self.set_chat_at_position(point, if index < rounded { 'O' } else { '.' });
             }
-            next_postion = test
         }
-
-        self.set_chat_at_position(&position, '.');
-
-        self.set_chat_at_position(&next_postion, 'O');
     }
 
-    fn cycle(&mut self) {
-        let north = Point { x: 0, y: -1 };
-        let west = Point { x: -1, y: 0 };
-        let south = Point { x: 0, y: 1 };
-        let east = Point { x: 1, y: 0 };
-
-        for direction in [north, west, south, east] {
-            self.step(&direction);
+    /* The positions of the rounded rocks, one bit per cell */
+    fn rounded_rocks(&self) -> Vec<u64> {
+        let mut bits = vec![0u64; (self.width() * self.height()).div_ceil(64)];
+        for (y, row) in self.maps.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                if *c == 'O' {
+                    let index = y * self.width() + x;
+                    bits[index / 64] |= 1 << (index % 64);
+                }
+            }
         }
+        bits
     }
 
-    fn step(&mut self, direction: &Point) {
-
-        let north = Point { x: 0, y: -1 };
-        let west = Point { x: -1, y: 0 };
-        let south = Point { x: 0, y: 1 };
-        let east = Point { x: 1, y: 0 };
-
-        if direction == &north  {
-            for y in 0..self.height() {
-                for x in 0..self.width() {
-                    let position = Point {
-                        x: x as i32,
-                        y: y as i32,
-                    };
-                    self.handle_step(&position, direction);
-                }
-            }
-        } else if direction == &west {
-            for y in 0..self.height() {
-                for x in 0..self.width() {
-                    let position = Point {
-                        x: x as i32,
-                        y: y as i32,
-                    };
-                    self.handle_step(&position, direction);
-                }
-            }
-        } else if direction == &south {
-            for y in (0..self.height()).rev() {
-                for x in 0..self.width() {
-                    let position = Point {
-                        x: x as i32,
-                        y: y as i32,
-                    };
-                    self.handle_step(&position, direction);
-                }
-            }
-        } else if direction == &east {
-            for y in 0..self.height() {
-                for x in (0..self.width()).rev() {
-                    let position = Point {
-                        x: x as i32,
-                        y: y as i32,
-                    };
-                    self.handle_step(&position, direction);
-                }
-            }
+    /* Tilts the dish so every rounded rock rolls as far towards the edge as it can */
+    fn step(&mut self, direction: Direction) {
+        let (lines, length) = if direction.is_vertical() {
+            (self.width(), self.height())
         } else {
-            panic!("Not supported")
+            (self.height(), self.width())
+        };
+
+        for across in 0..lines {
+            let line: Vec<Point> = (0..length).map(|along| {
+                direction.cell(across, along, self.width(), self.height())
+            }).collect();
+            self.slide(&line);
         }
     }
 }
@@ -192,12 +322,25 @@ struct Point {
     y: i32,
 }
 
-impl Point {
-    fn add(&self, other: &Point) -> Self {
-        Point {
-            x: self.x + other.x,
-            y: self.y + other.y,
-        }
+/* Draws the tilts of a --random plan */
+struct Rng {
+    state: u64
+}
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng { state: (seed ^ 0x9E37_79B9_7F4A_7C15) | 1 }
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        self.state.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
     }
 }
 
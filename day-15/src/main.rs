@@ -2,82 +2,467 @@ use std::collections::HashMap;
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
 use std::env;
+use std::hash::Hasher;
 
 fn main() -> io::Result<()> {
     let args: Vec<String> = env::args().collect();
     let input = &args[1];
 
-    let input = read_input(input)?;
+    println!("What is the sum of the results? {:?}", solve1(read_input(input)?)?);
 
-    println!("What is the sum of the results? {:?}", solve1(&input));
+    let config = HashConfig {
+        multiplier: numeric_flag(&args, "--multiplier").unwrap_or(HashConfig::HASH.multiplier),
+        modulus: numeric_flag(&args, "--modulus").unwrap_or(HashConfig::HASH.modulus),
+        initial: numeric_flag(&args, "--initial").unwrap_or(HashConfig::HASH.initial),
+    };
+    assert!(config.modulus > 0 && config.modulus <= 1 << 20,
+            "--modulus must be between 1 and 2^20 to fit the boxes in memory");
+
+    let after = args.iter()
+                    .position(|arg| arg == "--after")
+                    .map(|i| args[i + 1].parse::<usize>().expect("--after takes a step number"));
+
+    // Only the replay and the collision analysis need every step at once
+    let keep_steps = ["--collisions", "--box", "--label"].iter()
+                                                         .any(|flag| args.iter().any(|arg| arg == flag));
+    let configs = if config == HashConfig::HASH {
+        vec![HashConfig::HASH]
+    } else {
+        vec![HashConfig::HASH, config.clone()]
+    };
+
+    let initialization = solve2(read_input(input)?.steps(), &configs, keep_steps)?;
 
     println!("What is the focusing power of the resulting lens configuration? {:?}",
-             solve2(&input));
+             initialization.focusing_powers[0]);
+
+    if let Some(power) = initialization.focusing_powers.get(1) {
+        println!("With {:?} the focusing power is {}", config, power);
+    }
+
+    let steps = match initialization.steps {
+        Some(steps) => steps,
+        None => return Ok(()),
+    };
+
+    if args.iter().any(|arg| arg == "--collisions") {
+        print_collisions(&analyse_collisions(&steps, &config));
+    }
+
+    let replay = Replay::record(&steps, &config);
+    let after = after.unwrap_or(steps.len());
+
+    if let Some(i) = args.iter().position(|arg| arg == "--box") {
+        let box_nr: usize = args[i + 1].parse().expect("--box takes a box number");
+        assert!((box_nr as u64) < config.modulus, "There are only boxes 0 through {}", config.modulus - 1);
+        let lenses: Vec<String> = replay.box_after(box_nr, after)
+                                        .iter()
+                                        .map(|lens| format!("[{} {}]", lens.label, lens.focal))
+                                        .collect();
+        println!("After {} steps box {} holds: {}", after, box_nr, lenses.join(" "));
+    }
+
+    if let Some(i) = args.iter().position(|arg| arg == "--label") {
+        let label = &args[i + 1];
+        match replay.last_modified(label, after) {
+            Some(step) => println!("Within the first {} steps {} was last modified by step {}: {}",
+                                   after, label, step, steps[step - 1]),
+            None => println!("Within the first {} steps {} was never modified", after, label),
+        }
+    }
 
     Ok(())
 }
 
-fn solve1(input: &String) -> u32 {
-    input.split(',').map(|str| decode(str)).sum()
+fn solve1(instructions: impl Iterator<Item = Result<String, ParseError>>) -> Result<u64, ParseError> {
+    instructions.map(|instruction| instruction.map(|text| HashConfig::HASH.hash(&text))).sum()
+}
+
+struct Initialization {
+    /* The focusing power with the boxes arranged by each of the hash configurations */
+    focusing_powers: Vec<usize>,
+    steps: Option<Vec<Step>>,
 }
 
-fn solve2(input: &String) -> usize {
-    let mut hash_map: HashMap<u32, Vec<(String, usize)>> = HashMap::new();
-    (0..256).for_each(|i|{ hash_map.insert(i, Vec::new()); });
+/* Runs the sequence through one set of boxes per hash configuration in a single pass, only
+   holding on to the steps when asked to */
+fn solve2(steps: impl Iterator<Item = Result<Step, ParseError>>,
+          configs: &[HashConfig],
+          keep_steps: bool) -> Result<Initialization, ParseError> {
+    let mut boxes: Vec<LensBoxes> = configs.iter().map(LensBoxes::new).collect();
+    let mut kept: Vec<Step> = Vec::new();
 
-    for instruction in input.split(',') {
+    for step in steps {
+        let step = step?;
+        for lenses in boxes.iter_mut() {
+            lenses.apply(&step);
+        }
+        if keep_steps {
+            kept.push(step);
+        }
+    }
+
+    Ok(Initialization {
+        focusing_powers: boxes.iter().map(|lenses| lenses.focusing_power()).collect(),
+        steps: if keep_steps { Some(kept) } else { None },
+    })
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum Step {
+    Remove(String),
+    Insert(String, usize),
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+enum ParseError {
+    UnknownOperation(String),
+    /* Labels are one or more letters */
+    InvalidLabel(String),
+    /* Focal lengths are a single digit from 1 through 9 */
+    InvalidFocalLength(String),
+    /* A step longer than any sensible label, cut off at the limit */
+    TooLong(String),
+    Io(io::ErrorKind),
+}
+
+impl From<ParseError> for io::Error {
+    fn from(error: ParseError) -> io::Error {
+        match error {
+            ParseError::Io(kind) => io::Error::from(kind),
+            error => io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", error)),
+        }
+    }
+}
 
-        if instruction.contains('-') {
-            let label = String::from(instruction.split('-').next().unwrap());
-            let box_nr = decode(label.as_str());
-            let my_box: &mut Vec<(String, usize)> = hash_map.get_mut(&box_nr).unwrap();
-            let index_of_lens =
-                my_box.iter()
-                      .enumerate()
-                      .find(|(_index, (l, _))| &label == l)
-                      .map(|(index,_)| index);
-            if index_of_lens.is_some() {
-                my_box.remove(index_of_lens.unwrap());
+impl Step {
+    fn parse(instruction: &str) -> Result<Step, ParseError> {
+        let label = |label: &str| {
+            if !label.is_empty() && label.chars().all(|c| c.is_ascii_alphabetic()) {
+                Ok(String::from(label))
+            } else {
+                Err(ParseError::InvalidLabel(String::from(instruction)))
             }
+        };
+
+        if let Some(name) = instruction.strip_suffix('-') {
+            Ok(Step::Remove(label(name)?))
+        } else if let Some((name, focal)) = instruction.split_once('=') {
+            let focal = match focal.as_bytes() {
+                [digit @ b'1'..=b'9'] => (digit - b'0') as usize,
+                _ => return Err(ParseError::InvalidFocalLength(String::from(instruction))),
+            };
+            Ok(Step::Insert(label(name)?, focal))
         } else {
-            assert!(instruction.contains('='));
-            let mut it = instruction.split('=');
-            let label = String::from(it.next().unwrap());
-            let focal = it.next().unwrap().parse::<usize>().unwrap();
-            let box_nr = decode(label.as_str());
-            let my_box: &mut Vec<(String, usize)> = hash_map.get_mut(&box_nr).unwrap();
-            let index_of_lens =
-                my_box.iter()
-                      .enumerate()
-                      .find(|(_index, (l, _))| &label == l)
-                      .map(|(index,_)| index);
-            if index_of_lens.is_some() {
-                my_box[index_of_lens.unwrap()] = (label, focal)
-            } else {
-                my_box.push((label, focal))
+            Err(ParseError::UnknownOperation(String::from(instruction)))
+        }
+    }
+
+    fn label(&self) -> &str {
+        match self {
+            Step::Remove(label) => label,
+            Step::Insert(label, _) => label,
+        }
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Step::Remove(label) => write!(f, "{}-", label),
+            Step::Insert(label, focal) => write!(f, "{}={}", label, focal),
+        }
+    }
+}
+
+/* Reads the initialization sequence a step at a time, so only the current step is ever held in
+   memory. Newlines and other whitespace are ignored wherever they appear. Yields the text of
+   every step; steps() parses them as well. */
+struct StepReader<R: BufRead> {
+    reader: R,
+    token: Vec<u8>,
+    done: bool,
+}
+
+impl<R: BufRead> StepReader<R> {
+    const MAX_STEP_LENGTH: usize = 64;
+
+    fn new(reader: R) -> Self {
+        StepReader { reader, token: Vec::new(), done: false }
+    }
+
+    fn steps(self) -> impl Iterator<Item = Result<Step, ParseError>> {
+        self.map(|instruction| Step::parse(&instruction?))
+    }
+}
+
+impl<R: BufRead> Iterator for StepReader<R> {
+    type Item = Result<String, ParseError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None
+        }
+
+        self.token.clear();
+        let mut too_long = false;
+
+        loop {
+            let buffer = match self.reader.fill_buf() {
+                Ok(buffer) => buffer,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => {
+                    self.done = true;
+                    return Some(Err(ParseError::Io(error.kind())))
+                }
+            };
+
+            if buffer.is_empty() {
+                self.done = true;
+                break;
+            }
+
+            let (length, separated) = match buffer.iter().position(|byte| *byte == b',') {
+                Some(index) => (index, true),
+                None => (buffer.len(), false),
+            };
+
+            for byte in buffer[..length].iter().filter(|byte| !byte.is_ascii_whitespace()) {
+                if self.token.len() < Self::MAX_STEP_LENGTH {
+                    self.token.push(*byte);
+                } else {
+                    too_long = true;
+                }
+            }
+
+            self.reader.consume(length + separated as usize);
+            if separated {
+                break;
+            }
+        }
+
+        // Nothing after the last comma, or no input at all, is not a step
+        if self.done && self.token.is_empty() {
+            return None
+        }
+
+        let instruction = String::from_utf8_lossy(&self.token).into_owned();
+        if too_long {
+            Some(Err(ParseError::TooLong(instruction)))
+        } else {
+            Some(Ok(instruction))
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct Lens {
+    label: String,
+    focal: usize,
+}
+
+/* The boxes of the facility, one per hash value, each holding its lenses in slot order */
+#[derive(Clone, Debug)]
+struct LensBoxes {
+    config: HashConfig,
+    boxes: Vec<Vec<Lens>>,
+}
+
+impl LensBoxes {
+    fn new(config: &HashConfig) -> Self {
+        LensBoxes { config: config.clone(), boxes: vec![Vec::new(); config.modulus as usize] }
+    }
+
+    fn box_of(&self, label: &str) -> usize {
+        self.config.hash(label) as usize
+    }
+
+    /* Replaces the lens with the same label in place, or else adds it behind the others.
+       Returns whether the box changed. */
+    fn insert(&mut self, label: &str, focal: usize) -> bool {
+        let box_nr = self.box_of(label);
+        let lenses = &mut self.boxes[box_nr];
+        match lenses.iter_mut().find(|lens| lens.label == label) {
+            Some(lens) if lens.focal == focal => false,
+            Some(lens) => {
+                lens.focal = focal;
+                true
+            }
+            None => {
+                lenses.push(Lens { label: String::from(label), focal });
+                true
+            }
+        }
+    }
+
+    /* Takes the lens with the label out of its box, moving the lenses behind it forward.
+       Returns whether there was such a lens. */
+    fn remove(&mut self, label: &str) -> bool {
+        let box_nr = self.box_of(label);
+        let lenses = &mut self.boxes[box_nr];
+        match lenses.iter().position(|lens| lens.label == label) {
+            Some(index) => {
+                lenses.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn apply(&mut self, step: &Step) -> bool {
+        match step {
+            Step::Remove(label) => self.remove(label),
+            Step::Insert(label, focal) => self.insert(label, *focal),
+        }
+    }
+
+    fn focusing_power(&self) -> usize {
+        self.boxes.iter().enumerate().map(|(box_nr, lenses)| {
+            lenses.iter()
+                  .enumerate()
+                  .map(|(slot, lens)| (box_nr + 1) * (slot + 1) * lens.focal)
+                  .sum::<usize>()
+        }).sum()
+    }
+}
+
+/* The whole initialization sequence played back with a snapshot of a box every time a step
+   changed it, so any box can be looked up as it was after any step */
+struct Replay {
+    /* For every box: the step numbers that changed it, with its lenses after that step */
+    history: Vec<Vec<(usize, Vec<Lens>)>>,
+    /* For every label: the step numbers that changed its lens */
+    modifications: HashMap<String, Vec<usize>>,
+}
+
+impl Replay {
+    fn record(steps: &[Step], config: &HashConfig) -> Self {
+        let mut boxes = LensBoxes::new(config);
+        let mut history: Vec<Vec<(usize, Vec<Lens>)>> = vec![vec![(0, Vec::new())]; config.modulus as usize];
+        let mut modifications: HashMap<String, Vec<usize>> = HashMap::new();
+
+        for (index, step) in steps.iter().enumerate() {
+            if boxes.apply(step) {
+                let box_nr = boxes.box_of(step.label());
+                history[box_nr].push((index + 1, boxes.boxes[box_nr].clone()));
+                modifications.entry(String::from(step.label())).or_default().push(index + 1);
             }
         }
+
+        Replay { history, modifications }
+    }
+
+    /* The lenses in the box once the first after steps have run */
+    fn box_after(&self, box_nr: usize, after: usize) -> &[Lens] {
+        let snapshots = &self.history[box_nr];
+        let index = snapshots.partition_point(|(step, _)| *step <= after);
+        &snapshots[index - 1].1
+    }
+
+    /* The number of the last step among the first after steps that changed the label's lens */
+    fn last_modified(&self, label: &str, after: usize) -> Option<usize> {
+        let steps = self.modifications.get(label)?;
+        let index = steps.partition_point(|step| *step <= after);
+        index.checked_sub(1).map(|index| steps[index])
+    }
+}
+
+/* The parameters of the HASH algorithm: start from initial and for every byte add it, multiply
+   by multiplier and take the remainder modulo modulus */
+#[derive(Clone, PartialEq, Eq, Debug)]
+struct HashConfig {
+    multiplier: u64,
+    modulus: u64,
+    initial: u64,
+}
+
+impl HashConfig {
+    const HASH: HashConfig = HashConfig { multiplier: 17, modulus: 256, initial: 0 };
+
+    fn hasher(&self) -> HolidayHasher {
+        HolidayHasher { config: self.clone(), state: self.initial % self.modulus }
+    }
+
+    fn hash(&self, text: &str) -> u64 {
+        let mut hasher = self.hasher();
+        hasher.write(text.as_bytes());
+        hasher.finish()
+    }
+}
+
+struct HolidayHasher {
+    config: HashConfig,
+    state: u64,
+}
+
+impl Hasher for HolidayHasher {
+    fn finish(&self) -> u64 {
+        self.state
     }
 
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            let value = (self.state as u128 + *byte as u128) * self.config.multiplier as u128;
+            self.state = (value % self.config.modulus as u128) as u64;
+        }
+    }
+}
 
-    (0..256).map(|i| {
-        let my_box : &Vec<(String, usize)> = hash_map.get(&i).unwrap();
-        my_box.iter().enumerate().map(|(slot, (_label, focus))| ((i+1) as usize) * (slot+1) * focus).sum::<usize>()
-    }).sum::<usize>()
+struct CollisionReport {
+    buckets: u64,
+    labels: usize,
+    /* For every number of labels: how many buckets hold that many */
+    load_distribution: Vec<usize>,
+    /* Pairs of distinct labels that end up in the same bucket */
+    colliding_pairs: usize,
 }
 
-fn decode(str: &str) -> u32 {
-    let mut current_value = 0;
+/* How the distinct labels of the sequence spread over the buckets */
+fn analyse_collisions(steps: &[Step], config: &HashConfig) -> CollisionReport {
+    let mut labels: Vec<&str> = steps.iter().map(|step| step.label()).collect();
+    labels.sort();
+    labels.dedup();
+
+    let mut loads: HashMap<u64, usize> = HashMap::new();
+    for label in &labels {
+        *loads.entry(config.hash(label)).or_default() += 1;
+    }
+
+    let largest = loads.values().max().cloned().unwrap_or(0);
+    let mut load_distribution = vec![0; largest + 1];
+    load_distribution[0] = config.modulus as usize - loads.len();
+    for load in loads.values() {
+        load_distribution[*load] += 1;
+    }
+
+    CollisionReport {
+        buckets: config.modulus,
+        labels: labels.len(),
+        load_distribution,
+        colliding_pairs: loads.values().map(|load| load * (load - 1) / 2).sum(),
+    }
+}
 
-    for c in str.chars() {
-        current_value  += c as u32;
-        current_value  *= 17;
-        current_value  =  current_value - ((current_value / 256) * 256);
+fn print_collisions(report: &CollisionReport) {
+    let pairs = (report.labels * report.labels.saturating_sub(1) / 2) as f64;
+    println!("{} distinct labels over {} buckets", report.labels, report.buckets);
+    for (load, buckets) in report.load_distribution.iter().enumerate() {
+        if *buckets > 0 {
+            println!("  {:>6} buckets hold {} labels", buckets, load);
+        }
     }
-    current_value
+    println!("{} colliding pairs, against {:.1} expected from a uniform hash",
+             report.colliding_pairs, pairs / report.buckets as f64);
+}
+
+/* The value of a flag that takes a number, if it was given */
+fn numeric_flag(args: &[String], flag: &str) -> Option<u64> {
+    args.iter()
+        .position(|arg| arg == flag)
+        .map(|i| args[i + 1].parse().unwrap_or_else(|_| panic!("{} takes a number", flag)))
 }
 
-fn read_input(filename: &String) ->  io::Result<String> {
+fn read_input(filename: &String) ->  io::Result<StepReader<BufReader<File>>> {
     let file_in = File::open(filename)?;
-    Ok(BufReader::new(file_in).lines().next().unwrap().unwrap())
+    Ok(StepReader::new(BufReader::new(file_in)))
 }
@@ -1,4 +1,3 @@
-use std::cmp::max;
 use std::collections::{HashSet, VecDeque};
 use std::fs::File;
 use std::io::{self, BufReader, BufRead};
@@ -13,8 +12,14 @@ fn main() -> io::Result<()> {
     println!("How many tiles end up being energized? {:?}",
              solve1(&problem));
 
-    println!("How many tiles are energized in that configuration? {:?}",
-             solve2(&problem));
+    let (best, energized) = best_entry(&problem);
+    println!("How many tiles are energized in that configuration? {:?}", energized);
+
+    if args.iter().any(|arg| arg == "--entry") {
+        println!("The beam enters at ({}, {}) heading {}",
+                 best.position.x, best.position.y, direction_name(&best.direction));
+    }
+
     Ok(())
 }
 
@@ -35,45 +40,17 @@ fn solve1(problem: &Problem) -> usize {
 }
 
 
-fn solve2(problem: &Problem) -> usize {
-
-    let mut max_energization: usize = 0;
-    let going_right = Point { x: 1, y:0 };
-    let going_left = Point { x: -1, y:0 };
-    let going_up = Point { x: 0, y: -1 };
-    let going_down = Point { x: 0, y: 1 };
+/* The entry on the edge of the contraption that energizes the most tiles, and how many */
+fn best_entry(problem: &Problem) -> (State, usize) {
+    let graph = BeamGraph::new(problem);
+    let entries = problem.entries();
+    let counts = graph.energized_counts(&entries.iter().map(|entry| graph.index(entry)).collect::<Vec<_>>());
 
-    for y in 0..(problem.height()) {
-        for x in 0..problem.width() {
-            let mut allowed_directions: Vec<Point> = Vec::new();
-            if y == 0 {
-                allowed_directions.push(going_down.clone());
-            }
-            if y == problem.height() {
-                allowed_directions.push(going_up.clone());
-            }
-            if x == 0 {
-                allowed_directions.push(going_right.clone());
-            }
-            if x == problem.width() {
-                allowed_directions.push(going_left.clone());
-            }
-            for allowed_direction in allowed_directions {
-                let state = State {
-                    position: Point {
-                        x: x as i64,
-                        y: y as i64
-                    },
-                    direction: allowed_direction,
-                };
-                max_energization = max(max_energization,
-                                       solve_for_initial_state(problem,
-                                                                   state))
-            }
-        }
-    }
+    debug_assert_eq!(counts[0], solve_for_initial_state(problem, entries[0].clone()));
 
-    max_energization
+    // Ties go to the first entry, walking the edge clockwise from the top left corner
+    let best = (0..entries.len()).rev().max_by_key(|i| counts[*i]).unwrap();
+    (entries[best].clone(), counts[best])
 }
 
 fn solve_for_initial_state(problem: &Problem, state: State) -> usize {
@@ -95,7 +72,6 @@ fn solve_for_initial_state(problem: &Problem, state: State) -> usize {
             )
     }
 
-    //problem.debug(&seen_states);
     let energized_tiles: HashSet<Point> =
         HashSet::from_iter(seen_states.iter().map(|x| x.position.clone()));
 
@@ -109,13 +85,29 @@ struct Problem {
 impl Problem {
 
     fn width(&self) -> usize {
-        self.map.get(0).unwrap().len()
+        self.map.first().unwrap().len()
     }
 
     fn height(&self) -> usize {
         self.map.len()
     }
 
+    /* Every way a beam can enter: each edge tile heading into the contraption, corners twice */
+    fn entries(&self) -> Vec<State> {
+        let (width, height) = (self.width() as i64, self.height() as i64);
+        let entry = |x: i64, y: i64, dx: i64, dy: i64| State {
+            position: Point { x, y },
+            direction: Point { x: dx, y: dy },
+        };
+
+        let mut entries = Vec::new();
+        entries.extend((0..width).map(|x| entry(x, 0, 0, 1)));
+        entries.extend((0..height).map(|y| entry(width - 1, y, -1, 0)));
+        entries.extend((0..width).rev().map(|x| entry(x, height - 1, 0, -1)));
+        entries.extend((0..height).rev().map(|y| entry(0, y, 1, 0)));
+        entries
+    }
+
     fn is_on_map(&self, point: &Point) -> bool {
         point.x >= 0
         && point.y >= 0
@@ -169,29 +161,19 @@ impl Problem {
                 next_states.push(next_state)
             },
             '|' => {
-                if state.direction == going_right {
-                    next_states.push(state.change_direction(&going_up));
-                    next_states.push(state.change_direction(&going_down));
-                } else if state.direction == going_left {
+                if state.direction == going_right || state.direction == going_left {
                     next_states.push(state.change_direction(&going_up));
                     next_states.push(state.change_direction(&going_down));
-                } else if state.direction == going_up {
-                    next_states.push(state.clone());
-                } else if state.direction ==  going_down {
+                } else if state.direction == going_up || state.direction == going_down {
                     next_states.push(state.clone());
                 } else {
                     panic!("Unexpected stated")
                 }
             },
             '-' => {
-                if state.direction == going_right {
+                if state.direction == going_right || state.direction == going_left {
                     next_states.push(state.clone());
-                } else if state.direction == going_left {
-                    next_states.push(state.clone());
-                } else if state.direction == going_up {
-                    next_states.push(state.change_direction(&going_left));
-                    next_states.push(state.change_direction(&going_right));
-                } else if state.direction ==  going_down {
+                } else if state.direction == going_up || state.direction == going_down {
                     next_states.push(state.change_direction(&going_left));
                     next_states.push(state.change_direction(&going_right));
                 } else {
@@ -206,26 +188,6 @@ impl Problem {
                    .filter(|x|self.is_on_map(&x.position))
                    .collect()
     }
-
-    // fn debug(&self, energized: &HashSet<Point>) {
-    //     for y in 0..self.height() {
-    //         let  row: String =
-    //             self.map.get(y)
-    //                     .unwrap()
-    //                     .clone()
-    //                     .into_iter()
-    //                     .enumerate()
-    //                     .map(|(x,c)|{
-    //                         let  point = Point {x: x as i64,
-    //                                             y: y as i64 };
-    //                         if energized.contains( &point) { '#' }
-    //                         else { c }
-    //                     })
-    //                     .collect();
-    //         println!("{:?}", row);
-    //
-    //     }
-    // }
 }
 
 #[derive(PartialOrd, Ord, PartialEq, Eq, Clone, Hash, Debug)]
@@ -265,6 +227,176 @@ impl State {
     }
 }
 
+/* Every beam state (tile and heading) as a node, with an edge to each state it leads to */
+struct BeamGraph {
+    width: usize,
+    successors: Vec<Vec<usize>>,
+}
+
+const DIRECTIONS: [Point; 4] = [
+    Point { x: 0, y: -1 },
+    Point { x: 1, y: 0 },
+    Point { x: 0, y: 1 },
+    Point { x: -1, y: 0 },
+];
+
+fn direction_name(direction: &Point) -> &'static str {
+    match DIRECTIONS.iter().position(|d| d == direction) {
+        Some(0) => "up",
+        Some(1) => "right",
+        Some(2) => "down",
+        _ => "left",
+    }
+}
+
+impl BeamGraph {
+    fn new(problem: &Problem) -> Self {
+        let width = problem.width();
+        let mut graph = BeamGraph { width, successors: Vec::new() };
+
+        graph.successors = (0..width * problem.height() * 4).map(|index| {
+            let tile = index / 4;
+            let state = State {
+                position: Point { x: (tile % width) as i64, y: (tile / width) as i64 },
+                direction: DIRECTIONS[index % 4].clone(),
+            };
+            problem.calculate_next_state(&state).iter().map(|next| graph.index(next)).collect()
+        }).collect();
+
+        graph
+    }
+
+    fn index(&self, state: &State) -> usize {
+        let tile = state.position.y as usize * self.width + state.position.x as usize;
+        tile * 4 + DIRECTIONS.iter().position(|d| d == &state.direction).unwrap()
+    }
+
+    /* Tarjan's algorithm without recursion. Components are numbered in the order they are
+       completed, so every edge between components goes to a lower number. */
+    fn strongly_connected_components(&self) -> (Vec<usize>, usize) {
+        const UNVISITED: usize = usize::MAX;
+
+        let count = self.successors.len();
+        let mut order = vec![UNVISITED; count];
+        let mut low_link = vec![0; count];
+        let mut on_stack = vec![false; count];
+        let mut component = vec![UNVISITED; count];
+        let mut stack: Vec<usize> = Vec::new();
+        let mut components = 0;
+        let mut visited = 0;
+
+        for root in 0..count {
+            if order[root] != UNVISITED {
+                continue;
+            }
+
+            // Pairs of a node and how many of its successors have been looked at
+            let mut call_stack: Vec<(usize, usize)> = vec![(root, 0)];
+            order[root] = visited;
+            low_link[root] = visited;
+            visited += 1;
+            stack.push(root);
+            on_stack[root] = true;
+
+            while let Some((node, next)) = call_stack.pop() {
+                if let Some(&successor) = self.successors[node].get(next) {
+                    call_stack.push((node, next + 1));
+                    if order[successor] == UNVISITED {
+                        order[successor] = visited;
+                        low_link[successor] = visited;
+                        visited += 1;
+                        stack.push(successor);
+                        on_stack[successor] = true;
+                        call_stack.push((successor, 0));
+                    } else if on_stack[successor] {
+                        low_link[node] = low_link[node].min(order[successor]);
+                    }
+                    continue;
+                }
+
+                if low_link[node] == order[node] {
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack[member] = false;
+                        component[member] = components;
+                        if member == node {
+                            break;
+                        }
+                    }
+                    components += 1;
+                }
+
+                if let Some((parent, _)) = call_stack.last() {
+                    low_link[*parent] = low_link[*parent].min(low_link[node]);
+                }
+            }
+        }
+
+        (component, components)
+    }
+
+    /* How many tiles a beam starting in each of the states energizes. Every component gets the
+       set of tiles reachable from it as a bitset, built from the sets of the components it leads
+       to; a set is dropped as soon as every component leading into it has used it. */
+    fn energized_counts(&self, starts: &[usize]) -> Vec<usize> {
+        let (component, components) = self.strongly_connected_components();
+        let words = (self.successors.len() / 4).div_ceil(64);
+
+        let mut members: Vec<Vec<usize>> = vec![Vec::new(); components];
+        for (state, c) in component.iter().enumerate() {
+            members[*c].push(state);
+        }
+
+        let mut condensed: Vec<Vec<usize>> = vec![Vec::new(); components];
+        let mut pending = vec![0; components];
+        for (c, states) in members.iter().enumerate() {
+            let mut next: Vec<usize> = states.iter()
+                                             .flat_map(|state| self.successors[*state].iter())
+                                             .map(|successor| component[*successor])
+                                             .filter(|other| *other != c)
+                                             .collect();
+            next.sort();
+            next.dedup();
+            for other in &next {
+                pending[*other] += 1;
+            }
+            condensed[c] = next;
+        }
+
+        let mut wanted: Vec<bool> = vec![false; components];
+        for start in starts {
+            wanted[component[*start]] = true;
+        }
+
+        let mut tiles: Vec<Option<Vec<u64>>> = vec![None; components];
+        let mut counts: Vec<usize> = vec![0; components];
+
+        for c in 0..components {
+            let mut bits = vec![0u64; words];
+            for state in &members[c] {
+                bits[state / 4 / 64] |= 1 << (state / 4 % 64);
+            }
+
+            for other in &condensed[c] {
+                let other_bits = tiles[*other].as_ref().unwrap();
+                bits.iter_mut().zip(other_bits.iter()).for_each(|(word, other)| *word |= other);
+                pending[*other] -= 1;
+                if pending[*other] == 0 {
+                    tiles[*other] = None;
+                }
+            }
+
+            if wanted[c] {
+                counts[c] = bits.iter().map(|word| word.count_ones() as usize).sum();
+            }
+            if pending[c] > 0 {
+                tiles[c] = Some(bits);
+            }
+        }
+
+        starts.iter().map(|start| counts[component[*start]]).collect()
+    }
+}
 
 fn read_input(filename: &String) ->  io::Result<Problem> {
     let file_in = File::open(filename)?;